@@ -0,0 +1,126 @@
+/// In-memory backend for tests
+#[cfg(test)]
+pub(crate) mod fake;
+
+use anyhow::Result;
+
+use crate::fingerprint::WindowFingerprint;
+use crate::x11::WindowInfo;
+
+/// Window system operations needed to manage virtual desktops.
+///
+/// `X11Connection` is the real implementation; the test-only `FakeBackend`
+/// keeps everything in memory so desktop logic can be tested without an X server.
+/// Window IDs are toplevel windows as returned by `get_toplevel_windows`.
+pub trait Backend {
+    /// Display name without screen number (e.g. ":0")
+    fn display_name(&self) -> &str;
+
+    /// Screen number being managed
+    fn screen_num(&self) -> usize;
+
+    /// Get all toplevel application windows
+    fn get_toplevel_windows(&self) -> Result<Vec<u32>>;
+
+    /// Get info about all toplevel application windows
+    fn get_all_window_info(&self) -> Result<Vec<WindowInfo>>;
+
+    /// Get the application window inside a toplevel window (itself if unframed)
+    fn client_window(&self, window: u32) -> Result<u32>;
+
+    /// Get the toplevel window (frame) containing a window, such as a client
+    /// window or the focused window; toplevel windows map to themselves
+    fn toplevel_window(&self, window: u32) -> Result<u32>;
+
+    /// Get the toplevel window a toplevel window belongs with: the one its
+    /// client is transient for (WM_TRANSIENT_FOR), else its WM_HINTS window
+    /// group leader. None if it has neither or leads its own group.
+    fn get_window_leader(&self, window: u32) -> Result<Option<u32>>;
+
+    /// Get identifying properties of a toplevel window
+    fn get_window_fingerprint(&self, window: u32) -> Result<WindowFingerprint>;
+
+    /// Map (show) a window
+    fn map_window(&self, window: u32) -> Result<()>;
+
+    /// Unmap (hide) a window
+    fn unmap_window(&self, window: u32) -> Result<()>;
+
+    /// Get toplevel application windows in stacking order (bottom to top)
+    fn get_stacking_order(&self) -> Result<Vec<u32>>;
+
+    /// Restack windows to match the given order (bottom to top)
+    fn restack_windows(&self, order: &[u32]) -> Result<()>;
+
+    /// Get the currently focused window
+    fn get_active_window(&self) -> Result<u32>;
+
+    /// Give input focus to a toplevel window's client and publish it as
+    /// _NET_ACTIVE_WINDOW
+    fn focus_window(&self, window: u32) -> Result<()>;
+
+    /// Hand input focus back to the root (PointerRoot) and clear
+    /// _NET_ACTIVE_WINDOW
+    fn focus_root(&self) -> Result<()>;
+
+    /// Get a CARDINAL property from the root window
+    fn get_root_property(&self, name: &[u8]) -> Result<Option<u32>>;
+
+    /// Set a CARDINAL property on the root window
+    fn set_root_property(&self, name: &[u8], value: u32) -> Result<()>;
+
+    /// Get a CARDINAL property from a window
+    fn get_window_property(&self, window: u32, name: &[u8]) -> Result<Option<u32>>;
+
+    /// Set a CARDINAL property on a window
+    fn set_window_property(&self, window: u32, name: &[u8], value: u32) -> Result<()>;
+
+    /// Set a CARDINAL property on the root window unless it already has
+    /// that value, so clients watching it aren't woken for nothing
+    fn update_root_property(&self, name: &[u8], value: u32) -> Result<()> {
+        if self.get_root_property(name)? != Some(value) {
+            self.set_root_property(name, value)?;
+        }
+        Ok(())
+    }
+
+    /// Set a CARDINAL property on a window unless it already has that value
+    /// (see `update_root_property`)
+    fn update_window_property(&self, window: u32, name: &[u8], value: u32) -> Result<()> {
+        // A window that is gone can't be read; the write then fails or not
+        // exactly as it would have without the check
+        if self.get_window_property(window, name).unwrap_or(None) != Some(value) {
+            self.set_window_property(window, name, value)?;
+        }
+        Ok(())
+    }
+
+    /// Set a WINDOW list property on the root window
+    fn set_root_windows(&self, name: &[u8], windows: &[u32]) -> Result<()>;
+
+    /// Set an ATOM list property on the root window
+    fn set_root_atoms(&self, name: &[u8], atoms: &[&[u8]]) -> Result<()>;
+
+    /// Set a UTF8_STRING list property on the root window
+    fn set_root_strings(&self, name: &[u8], strings: &[String]) -> Result<()>;
+
+    /// Create the _NET_SUPPORTING_WM_CHECK window if it doesn't exist yet
+    fn ensure_wm_check_window(&self) -> Result<()>;
+
+    /// Whether a running EWMH window manager provides its own desktops,
+    /// in which case desktop changes must be requested from it
+    fn has_ewmh_desktops(&self) -> Result<bool>;
+
+    /// Send an EWMH client message to the root window about `window`
+    /// (or the root window itself if None)
+    fn send_client_message(&self, window: Option<u32>, name: &[u8], data: [u32; 5]) -> Result<()>;
+
+    /// Get the atom for a name, creating it if needed
+    fn intern_atom(&self, name: &[u8]) -> Result<u32>;
+
+    /// Get an ATOM list property from a window (empty if unset)
+    fn get_window_atoms(&self, window: u32, name: &[u8]) -> Result<Vec<u32>>;
+
+    /// Set an ATOM list property on a window
+    fn set_window_atoms(&self, window: u32, name: &[u8], atoms: &[u32]) -> Result<()>;
+}
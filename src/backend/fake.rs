@@ -0,0 +1,369 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+
+use super::Backend;
+use crate::fingerprint::WindowFingerprint;
+use crate::x11::{WindowInfo, WmState};
+
+/// An operation performed on the fake backend that changes window state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Call {
+    /// `map_window(window)`
+    Map(u32),
+    /// `unmap_window(window)`
+    Unmap(u32),
+    /// `restack_windows(order)`
+    Restack(Vec<u32>),
+    /// `focus_window(window)`
+    Focus(u32),
+    /// `focus_root()`
+    FocusRoot,
+    /// `set_root_property(name, value)`
+    SetRootProperty(String, u32),
+    /// `set_window_property(window, name, value)`
+    SetWindowProperty(u32, String, u32),
+    /// `set_root_windows(name, windows)`
+    SetRootWindows(String, Vec<u32>),
+    /// `set_root_atoms(name, atoms)`
+    SetRootAtoms(String, Vec<String>),
+    /// `set_root_strings(name, strings)`
+    SetRootStrings(String, Vec<String>),
+    /// `ensure_wm_check_window()` when the window didn't exist yet
+    CreateWmCheckWindow,
+    /// `set_window_atoms(window, name, atoms)`
+    SetWindowAtoms(u32, String, Vec<u32>),
+    /// `send_client_message(window, name, data)`
+    ClientMessage(Option<u32>, String, [u32; 5]),
+}
+
+/// A window in the fake backend.
+#[derive(Debug, Clone, Default)]
+pub struct FakeWindow {
+    /// Window ID
+    pub id: u32,
+    /// Window title
+    pub name: String,
+    /// Whether the window is mapped
+    pub mapped: bool,
+    /// Identifying properties
+    pub fingerprint: WindowFingerprint,
+    /// CARDINAL properties by name
+    pub properties: HashMap<String, u32>,
+    /// Client window inside this frame, if it is one
+    pub client: Option<u32>,
+    /// ICCCM WM_STATE set by the window manager
+    pub wm_state: Option<WmState>,
+    /// ATOM list properties by name
+    pub atom_lists: HashMap<String, Vec<u32>>,
+    /// Toplevel window this one is transient for or grouped under
+    pub leader: Option<u32>,
+}
+
+/// In-memory backend that records every state-changing call.
+///
+/// Windows are kept in stacking order (bottom to top), and map, unmap,
+/// restack and property calls update that model so later queries see
+/// their effect, as they would on a real X server.
+#[derive(Debug, Default)]
+pub struct FakeBackend {
+    windows: RefCell<Vec<FakeWindow>>,
+    root_properties: RefCell<HashMap<String, u32>>,
+    root_windows: RefCell<HashMap<String, Vec<u32>>>,
+    root_atoms: RefCell<HashMap<String, Vec<String>>>,
+    root_strings: RefCell<HashMap<String, Vec<String>>>,
+    wm_check_window: RefCell<bool>,
+    ewmh_desktops: RefCell<bool>,
+    atoms: RefCell<Vec<String>>,
+    active: RefCell<Option<u32>>,
+    calls: RefCell<Vec<Call>>,
+}
+
+impl FakeBackend {
+    /// Create an empty fake display
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a mapped window on top of the stack
+    pub fn add_window(&self, id: u32, name: &str) {
+        self.add_fake_window(FakeWindow {
+            id,
+            name: name.to_string(),
+            mapped: true,
+            ..FakeWindow::default()
+        });
+    }
+
+    /// Add an arbitrary window on top of the stack
+    pub fn add_fake_window(&self, window: FakeWindow) {
+        self.windows.borrow_mut().push(window);
+    }
+
+    /// Remove a window, as if it had been destroyed
+    pub fn destroy_window(&self, id: u32) {
+        self.windows.borrow_mut().retain(|w| w.id != id);
+    }
+
+    /// Set the focused window
+    pub fn set_active_window(&self, id: Option<u32>) {
+        *self.active.borrow_mut() = id;
+    }
+
+    /// Pretend an EWMH window manager with desktop support is running
+    pub fn set_ewmh_desktops(&self, enabled: bool) {
+        *self.ewmh_desktops.borrow_mut() = enabled;
+    }
+
+    /// Change a window's WM_STATE, as the window manager would
+    pub fn set_wm_state(&self, id: u32, wm_state: Option<WmState>) {
+        let _ = self.with_window(id, |w| w.wm_state = wm_state);
+    }
+
+    /// Check if a window is mapped
+    pub fn is_mapped(&self, id: u32) -> bool {
+        self.windows.borrow().iter().any(|w| w.id == id && w.mapped)
+    }
+
+    /// Get a WINDOW list property from the root window
+    pub fn root_windows(&self, name: &str) -> Option<Vec<u32>> {
+        self.root_windows.borrow().get(name).cloned()
+    }
+
+    /// Get an ATOM list property from the root window
+    pub fn root_atoms(&self, name: &str) -> Option<Vec<String>> {
+        self.root_atoms.borrow().get(name).cloned()
+    }
+
+    /// Get a UTF8_STRING list property from the root window
+    pub fn root_strings(&self, name: &str) -> Option<Vec<String>> {
+        self.root_strings.borrow().get(name).cloned()
+    }
+
+    /// All calls recorded so far
+    pub fn calls(&self) -> Vec<Call> {
+        self.calls.borrow().clone()
+    }
+
+    /// Forget recorded calls
+    pub fn clear_calls(&self) {
+        self.calls.borrow_mut().clear();
+    }
+
+    fn with_window<T>(&self, id: u32, f: impl FnOnce(&mut FakeWindow) -> T) -> Result<T> {
+        let mut windows = self.windows.borrow_mut();
+        let window = windows
+            .iter_mut()
+            .find(|w| w.id == id)
+            .ok_or_else(|| anyhow!("BadWindow 0x{:x}", id))?;
+        Ok(f(window))
+    }
+}
+
+impl Backend for FakeBackend {
+    fn display_name(&self) -> &str {
+        ":fake"
+    }
+
+    fn screen_num(&self) -> usize {
+        0
+    }
+
+    fn get_toplevel_windows(&self) -> Result<Vec<u32>> {
+        Ok(self.windows.borrow().iter().map(|w| w.id).collect())
+    }
+
+    fn get_all_window_info(&self) -> Result<Vec<WindowInfo>> {
+        Ok(self
+            .windows
+            .borrow()
+            .iter()
+            .map(|w| WindowInfo {
+                id: w.id,
+                name: w.name.clone(),
+                is_mapped: w.mapped,
+                wm_state: w.wm_state,
+            })
+            .collect())
+    }
+
+    fn client_window(&self, window: u32) -> Result<u32> {
+        self.with_window(window, |w| w.client.unwrap_or(w.id))
+    }
+
+    fn toplevel_window(&self, window: u32) -> Result<u32> {
+        self.windows
+            .borrow()
+            .iter()
+            .find(|w| w.id == window || w.client == Some(window))
+            .map(|w| w.id)
+            .ok_or_else(|| anyhow!("BadWindow 0x{:x}", window))
+    }
+
+    fn get_window_leader(&self, window: u32) -> Result<Option<u32>> {
+        self.with_window(window, |w| w.leader)
+    }
+
+    fn get_window_fingerprint(&self, window: u32) -> Result<WindowFingerprint> {
+        self.with_window(window, |w| w.fingerprint.clone())
+    }
+
+    fn map_window(&self, window: u32) -> Result<()> {
+        self.calls.borrow_mut().push(Call::Map(window));
+        self.with_window(window, |w| w.mapped = true)
+    }
+
+    fn unmap_window(&self, window: u32) -> Result<()> {
+        self.calls.borrow_mut().push(Call::Unmap(window));
+        self.with_window(window, |w| w.mapped = false)
+    }
+
+    fn get_stacking_order(&self) -> Result<Vec<u32>> {
+        self.get_toplevel_windows()
+    }
+
+    fn restack_windows(&self, order: &[u32]) -> Result<()> {
+        self.calls.borrow_mut().push(Call::Restack(order.to_vec()));
+
+        // Place each window directly above the previous one, like
+        // ConfigureWindow with StackMode::Above and a sibling
+        let mut windows = self.windows.borrow_mut();
+        for pair in order.windows(2) {
+            let (sibling, window) = (pair[0], pair[1]);
+            let Some(from) = windows.iter().position(|w| w.id == window) else {
+                continue;
+            };
+            let moved = windows.remove(from);
+            match windows.iter().position(|w| w.id == sibling) {
+                Some(to) => windows.insert(to + 1, moved),
+                None => windows.insert(from, moved),
+            }
+        }
+        Ok(())
+    }
+
+    fn get_active_window(&self) -> Result<u32> {
+        self.active.borrow().ok_or_else(|| anyhow!("No window focused"))
+    }
+
+    fn focus_window(&self, window: u32) -> Result<()> {
+        self.calls.borrow_mut().push(Call::Focus(window));
+        self.set_active_window(Some(window));
+        Ok(())
+    }
+
+    fn focus_root(&self) -> Result<()> {
+        self.calls.borrow_mut().push(Call::FocusRoot);
+        self.set_active_window(None);
+        Ok(())
+    }
+
+    fn get_root_property(&self, name: &[u8]) -> Result<Option<u32>> {
+        let name = String::from_utf8_lossy(name).to_string();
+        Ok(self.root_properties.borrow().get(&name).copied())
+    }
+
+    fn set_root_property(&self, name: &[u8], value: u32) -> Result<()> {
+        let name = String::from_utf8_lossy(name).to_string();
+        self.calls
+            .borrow_mut()
+            .push(Call::SetRootProperty(name.clone(), value));
+        self.root_properties.borrow_mut().insert(name, value);
+        Ok(())
+    }
+
+    fn get_window_property(&self, window: u32, name: &[u8]) -> Result<Option<u32>> {
+        let name = String::from_utf8_lossy(name).to_string();
+        self.with_window(window, |w| w.properties.get(&name).copied())
+    }
+
+    fn set_window_property(&self, window: u32, name: &[u8], value: u32) -> Result<()> {
+        let name = String::from_utf8_lossy(name).to_string();
+        self.calls
+            .borrow_mut()
+            .push(Call::SetWindowProperty(window, name.clone(), value));
+        self.with_window(window, |w| {
+            w.properties.insert(name, value);
+        })
+    }
+
+    fn set_root_windows(&self, name: &[u8], windows: &[u32]) -> Result<()> {
+        let name = String::from_utf8_lossy(name).to_string();
+        self.calls
+            .borrow_mut()
+            .push(Call::SetRootWindows(name.clone(), windows.to_vec()));
+        self.root_windows.borrow_mut().insert(name, windows.to_vec());
+        Ok(())
+    }
+
+    fn set_root_atoms(&self, name: &[u8], atoms: &[&[u8]]) -> Result<()> {
+        let name = String::from_utf8_lossy(name).to_string();
+        let atoms: Vec<String> = atoms
+            .iter()
+            .map(|atom| String::from_utf8_lossy(atom).to_string())
+            .collect();
+        self.calls
+            .borrow_mut()
+            .push(Call::SetRootAtoms(name.clone(), atoms.clone()));
+        self.root_atoms.borrow_mut().insert(name, atoms);
+        Ok(())
+    }
+
+    fn set_root_strings(&self, name: &[u8], strings: &[String]) -> Result<()> {
+        let name = String::from_utf8_lossy(name).to_string();
+        self.calls
+            .borrow_mut()
+            .push(Call::SetRootStrings(name.clone(), strings.to_vec()));
+        self.root_strings.borrow_mut().insert(name, strings.to_vec());
+        Ok(())
+    }
+
+    fn ensure_wm_check_window(&self) -> Result<()> {
+        if !self.wm_check_window.replace(true) {
+            self.calls.borrow_mut().push(Call::CreateWmCheckWindow);
+        }
+        Ok(())
+    }
+
+    fn has_ewmh_desktops(&self) -> Result<bool> {
+        Ok(*self.ewmh_desktops.borrow())
+    }
+
+    fn send_client_message(&self, window: Option<u32>, name: &[u8], data: [u32; 5]) -> Result<()> {
+        let name = String::from_utf8_lossy(name).to_string();
+        self.calls
+            .borrow_mut()
+            .push(Call::ClientMessage(window, name, data));
+        Ok(())
+    }
+
+    fn intern_atom(&self, name: &[u8]) -> Result<u32> {
+        let name = String::from_utf8_lossy(name).to_string();
+        let mut atoms = self.atoms.borrow_mut();
+        let index = match atoms.iter().position(|a| *a == name) {
+            Some(index) => index,
+            None => {
+                atoms.push(name);
+                atoms.len() - 1
+            }
+        };
+        // Predefined atoms occupy the low IDs on a real server
+        Ok(index as u32 + 1000)
+    }
+
+    fn get_window_atoms(&self, window: u32, name: &[u8]) -> Result<Vec<u32>> {
+        let name = String::from_utf8_lossy(name).to_string();
+        self.with_window(window, |w| w.atom_lists.get(&name).cloned().unwrap_or_default())
+    }
+
+    fn set_window_atoms(&self, window: u32, name: &[u8], atoms: &[u32]) -> Result<()> {
+        let name = String::from_utf8_lossy(name).to_string();
+        self.calls
+            .borrow_mut()
+            .push(Call::SetWindowAtoms(window, name.clone(), atoms.to_vec()));
+        self.with_window(window, |w| {
+            w.atom_lists.insert(name, atoms.to_vec());
+        })
+    }
+}
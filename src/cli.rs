@@ -1,4 +1,5 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use xdeskie::hotkeys::Binding;
 
 #[derive(Parser)]
 #[command(name = "xdeskie")]
@@ -10,26 +11,45 @@ pub struct Args {
 
 #[derive(Subcommand)]
 pub enum Command {
-    /// Switch to desktop N (1-indexed)
-    Switch { desktop: u32 },
+    /// Switch to desktop N (1-indexed) or by name
+    Switch { desktop: String },
 
-    /// Switch to next desktop (wraps around)
+    /// Switch to next desktop (wraps around unless desktops.wrap is false)
     Next,
 
-    /// Switch to previous desktop (wraps around)
+    /// Switch to previous desktop (wraps around unless desktops.wrap is false)
     Prev,
 
     /// Move window to desktop N (0 = sticky/all desktops)
     Move {
         /// Window ID (hex like 0x1234, decimal, or "active")
         window: String,
-        /// Target desktop (0 = sticky, 1+ = specific desktop)
-        desktop: u32,
+        /// Target desktop (0 or "sticky" = sticky, 1+ or name = specific desktop)
+        desktop: String,
     },
 
     /// Set the number of desktops
     SetDesktops { count: u32 },
 
+    /// Name desktop N (an empty name removes it)
+    Rename {
+        /// Desktop number (1-indexed) or current name
+        desktop: String,
+        name: String,
+    },
+
+    /// Hide all windows on the current desktop, or bring them back
+    ShowDesktop {
+        #[arg(value_enum, default_value_t = ShowDesktopMode::Toggle)]
+        mode: ShowDesktopMode,
+    },
+
+    /// Undo the last desktop or window operation
+    Undo,
+
+    /// Redo the last undone operation
+    Redo,
+
     /// List all desktops
     List,
 
@@ -44,4 +64,98 @@ pub enum Command {
 
     /// Show a pager UI for switching desktops
     Gui,
+
+    /// Stay running and act on EWMH desktop requests from wmctrl, xdotool and panels
+    Daemon {
+        /// Bind Mod4+1..9 to switch desktops and Mod4+Shift+1..9 to move the active window
+        #[arg(long)]
+        default_keys: bool,
+        /// Bind a key combo to switch N, move N, next, prev or show-desktop (repeatable)
+        #[arg(long = "bind", value_name = "KEYS=ACTION")]
+        bindings: Vec<Binding>,
+    },
+
+    /// Print a JSON line for every desktop switch, window move, count change or urgent window
+    Subscribe {
+        /// Kinds of change to report, comma-separated
+        #[arg(long, value_enum, value_delimiter = ',', default_values_t = [
+            SubscribeEvent::Desktop,
+            SubscribeEvent::Window,
+            SubscribeEvent::Count,
+            SubscribeEvent::Urgent,
+        ])]
+        events: Vec<SubscribeEvent>,
+    },
+
+    /// Save or restore running applications and their desktops
+    Session {
+        #[command(subcommand)]
+        action: SessionAction,
+    },
+
+    /// Manage saved per-display state files
+    State {
+        #[command(subcommand)]
+        action: StateAction,
+    },
+
+    /// Check or print ~/.config/xdeskie/config.toml
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ShowDesktopMode {
+    /// Hide the windows
+    On,
+    /// Restore the hidden windows
+    Off,
+    /// Switch between the two
+    Toggle,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SubscribeEvent {
+    /// The current desktop changed
+    Desktop,
+    /// A window was assigned to a desktop or moved
+    Window,
+    /// The number of desktops changed
+    Count,
+    /// A window asks for attention
+    Urgent,
+}
+
+#[derive(Subcommand)]
+pub enum SessionAction {
+    /// Record running applications with their desktops and geometry
+    Save { name: String },
+
+    /// Relaunch a saved session's applications onto their desktops
+    Restore {
+        name: String,
+        /// Seconds to wait for launched windows to appear
+        #[arg(long, default_value_t = 30)]
+        timeout: u64,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum StateAction {
+    /// List saved states for all displays
+    List,
+
+    /// Remove saved states for displays that no longer exist
+    Prune,
+}
+
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    /// Validate the config file
+    Check,
+
+    /// Print the effective configuration, defaults included
+    Dump,
 }
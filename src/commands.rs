@@ -1,5 +1,26 @@
+/// Checking and printing the config file
+mod config;
+/// Switching, counting and naming desktops
 pub mod desktop;
+/// Undo and redo
+mod history;
+/// Saving and restoring application sessions
+mod session;
+/// Housekeeping of per-display state files
+mod states;
+/// Moving and listing windows
 pub mod window;
 
-pub use desktop::{list_desktops, print_current_desktop, set_desktop_count, switch_to_desktop};
+pub use desktop::{
+    list_desktops, print_current_desktop, record_focus, rename_desktop, set_desktop_count,
+    show_desktop, switch_to_desktop, track_new_windows,
+};
+#[doc(hidden)]
+pub use config::{check_config, dump_config};
+#[doc(hidden)]
+pub use history::{redo, undo};
+#[doc(hidden)]
+pub use session::{restore_session, save_session};
+#[doc(hidden)]
+pub use states::{list_states, prune_states};
 pub use window::{list_windows, move_window, parse_window_id};
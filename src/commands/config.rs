@@ -0,0 +1,23 @@
+use anyhow::Result;
+
+use crate::config::Config;
+
+/// Validate the config file and report where it was read from.
+pub fn check_config() -> Result<()> {
+    let path = Config::path()?;
+    if !path.exists() {
+        println!("No config file at {}; using defaults", path.display());
+        return Ok(());
+    }
+
+    Config::load()?;
+    println!("{} is valid", path.display());
+    Ok(())
+}
+
+/// Print the effective configuration, defaults included, as TOML.
+pub fn dump_config() -> Result<()> {
+    let config = Config::load_or_default();
+    print!("{}", toml::to_string(&config)?);
+    Ok(())
+}
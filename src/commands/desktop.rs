@@ -1,7 +1,9 @@
 use anyhow::{anyhow, Result};
 
+use crate::backend::Backend;
+use crate::ewmh;
 use crate::state::DesktopState;
-use crate::x11::X11Connection;
+use crate::x11::{WindowInfo, WmState};
 
 /// Switch to a specific desktop (0-indexed internally).
 ///
@@ -11,66 +13,318 @@ use crate::x11::X11Connection;
 /// - Cleaning up dead windows from state
 /// - Mapping/unmapping windows based on target desktop visibility
 /// - Preserving window stacking order per desktop
-pub fn switch_to_desktop(x11: &X11Connection, state: &mut DesktopState, target: u32) -> Result<()> {
-    let infos = x11.get_all_window_info()?;
-    let window_ids: Vec<u32> = infos.iter().map(|i| i.id).collect();
+pub fn switch_to_desktop(backend: &impl Backend, state: &mut DesktopState, target: u32) -> Result<()> {
+    state.transaction(backend, |state| {
+        record_switch(state, target);
+        apply_switch(backend, state, target)
+    })
+}
 
-    detect_new_windows(state, &infos);
-    state.cleanup_dead_windows(&window_ids);
+/// Record a switch in the undo history, unless it would change nothing.
+pub(crate) fn record_switch(state: &mut DesktopState, target: u32) {
+    if target != state.current {
+        state.record_history(format!("switch to desktop {}", target + 1));
+    }
+}
+
+/// Perform a desktop switch on already-locked state.
+pub(crate) fn apply_switch(backend: &impl Backend, state: &mut DesktopState, target: u32) -> Result<()> {
+    if backend.has_ewmh_desktops()? {
+        // The window manager shows and hides windows itself
+        ewmh::request_switch(backend, target)?;
+        state.current = target;
+        return Ok(());
+    }
+
+    let infos = backend.get_all_window_info()?;
+    track_windows(backend, state, &infos);
+    save_focus(backend, state);
 
-    // Save current desktop's stacking order before switching
-    save_stacking_order(x11, state, state.current)?;
+    // Switching ends show-desktop; the stacking order was saved when it began
+    if !end_show_desktop(state) {
+        // Save current desktop's stacking order before switching
+        save_stacking_order(backend, state, state.current)?;
+    }
 
-    update_window_visibility(x11, state, &infos, target)?;
+    update_window_visibility(backend, state, &infos, target)?;
 
     // Restore target desktop's stacking order
-    restore_stacking_order(x11, state, target)?;
+    restore_stacking_order(backend, state, target)?;
 
     state.current = target;
-    state.sync_to_x(x11)?;
-    state.save()?;
+    restore_focus(backend, state, &infos)?;
+
+    Ok(())
+}
+
+/// Remember which window has focus on the current desktop.
+fn save_focus(backend: &impl Backend, state: &mut DesktopState) {
+    let Ok(window) = backend.get_active_window().and_then(|w| backend.toplevel_window(w)) else {
+        return;
+    };
+    note_focus(state, window);
+}
+
+/// Record a focus change, if the window belongs on the current desktop.
+fn note_focus(state: &mut DesktopState, window: u32) {
+    if state.windows.contains_key(&window.to_string()) && state.is_visible_on(window, state.current) {
+        state.remember_focus(state.current, window);
+    }
+}
+
+/// Record that a window received focus, as seen by `xdeskie daemon`, and
+/// publish it as _NET_ACTIVE_WINDOW unless a window manager does.
+///
+/// Focus changes far more often than anything else, so it is only noted in
+/// `state` and written out with the next transaction.
+pub fn record_focus(backend: &impl Backend, state: &mut DesktopState, window: u32) -> Result<()> {
+    let window = backend.toplevel_window(window)?;
+    if !backend.has_ewmh_desktops()? {
+        ewmh::publish_active_window(backend, window)?;
+    }
+    note_focus(state, window);
+    Ok(())
+}
+
+/// Focus the window last focused on the current desktop, falling back to
+/// the topmost window in its saved stacking order, then to the topmost
+/// visible window for desktops never visited before. An empty desktop
+/// gets focus on the root.
+fn restore_focus(backend: &impl Backend, state: &DesktopState, infos: &[WindowInfo]) -> Result<()> {
+    let desktop = state.current;
+    let exists = |id: &u32| infos.iter().any(|info| info.id == *id);
+
+    let stacked = state
+        .stacking
+        .get(&desktop)
+        .into_iter()
+        .flatten()
+        .rev()
+        .filter_map(|k| k.parse().ok());
+    let candidate = state
+        .remembered_focus(desktop)
+        .into_iter()
+        .chain(stacked)
+        .chain(infos.iter().rev().map(|info| info.id))
+        .find(|id| exists(id) && state.is_visible_on(*id, desktop));
+
+    match candidate {
+        Some(window) => backend.focus_window(window),
+        // Otherwise focus would stay on a window that was just unmapped
+        None => backend.focus_root(),
+    }
+}
+
+/// Hide every window on the current desktop, or bring them back.
+///
+/// `show` of None toggles. Sticky windows are hidden too, so the root
+/// window can be reached. Returns whether the desktop is now shown.
+pub fn show_desktop(backend: &impl Backend, state: &mut DesktopState, show: Option<bool>) -> Result<bool> {
+    state.transaction(backend, |state| {
+        let show = show.unwrap_or(!state.showing_desktop);
+        apply_show_desktop(backend, state, show)?;
+        Ok(show)
+    })
+}
+
+/// Enter or leave show-desktop mode on already-locked state.
+pub(crate) fn apply_show_desktop(backend: &impl Backend, state: &mut DesktopState, show: bool) -> Result<()> {
+    if show == state.showing_desktop {
+        return Ok(());
+    }
+
+    if backend.has_ewmh_desktops()? {
+        ewmh::request_showing_desktop(backend, show)?;
+        state.showing_desktop = show;
+        return Ok(());
+    }
+
+    let infos = backend.get_all_window_info()?;
+    track_windows(backend, state, &infos);
+
+    if show {
+        save_stacking_order(backend, state, state.current)?;
+        for info in &infos {
+            if info.is_mapped && state.is_visible_on(info.id, state.current) {
+                state.set_desktop_hidden(info.id, true);
+                backend.unmap_window(info.id)?;
+            }
+        }
+        state.showing_desktop = true;
+    } else {
+        let hidden: Vec<u32> = state.desktop_hidden.iter().filter_map(|k| k.parse().ok()).collect();
+        end_show_desktop(state);
+        for window in hidden {
+            if state.is_visible_on(window, state.current) {
+                backend.map_window(window)?;
+            }
+        }
+        restore_stacking_order(backend, state, state.current)?;
+    }
 
     Ok(())
 }
 
+/// Leave show-desktop mode without remapping anything, returning whether
+/// it was active. Hidden windows become ordinary desktop windows again.
+fn end_show_desktop(state: &mut DesktopState) -> bool {
+    state.desktop_hidden.clear();
+    std::mem::replace(&mut state.showing_desktop, false)
+}
+
+/// Bring state in line with the windows that currently exist.
+///
+/// Forgets windows that have been destroyed and assigns newly appeared ones.
+pub fn track_windows(backend: &impl Backend, state: &mut DesktopState, infos: &[WindowInfo]) {
+    let window_ids: Vec<u32> = infos.iter().map(|i| i.id).collect();
+    state.cleanup_dead_windows(&window_ids);
+    detect_new_windows(backend, state, infos);
+    update_wm_states(state, infos);
+}
+
+/// Assign windows that appeared since state was last updated and forget
+/// destroyed ones, without switching desktops.
+///
+/// Used by the daemon on every MapNotify, CreateNotify and DestroyNotify so
+/// state never lags behind the display.
+pub fn track_new_windows(backend: &impl Backend, state: &mut DesktopState) -> Result<()> {
+    state.transaction(backend, |state| apply_track_new_windows(backend, state))
+}
+
+/// Track windows on already-locked state, hiding new windows that were
+/// assigned to a desktop other than the current one.
+pub(crate) fn apply_track_new_windows(backend: &impl Backend, state: &mut DesktopState) -> Result<()> {
+    // A window that was created but never mapped has no hints yet; it is
+    // assigned when it maps, so a _NET_WM_DESKTOP set before mapping counts
+    let infos: Vec<WindowInfo> = backend
+        .get_all_window_info()?
+        .into_iter()
+        .filter(|info| {
+            info.is_mapped
+                || info.wm_state.is_some()
+                || state.windows.contains_key(&info.id.to_string())
+        })
+        .collect();
+
+    let new: Vec<u32> = infos
+        .iter()
+        .map(|info| info.id)
+        .filter(|id| !state.windows.contains_key(&id.to_string()))
+        .collect();
+    track_windows(backend, state, &infos);
+
+    // An EWMH window manager places new windows on their desktops itself
+    if backend.has_ewmh_desktops()? {
+        return Ok(());
+    }
+    for info in infos.iter().filter(|info| new.contains(&info.id)) {
+        if info.is_mapped && !state.is_iconified(info.id) && !state.is_visible_on(info.id, state.current) {
+            backend.unmap_window(info.id)?;
+        }
+    }
+    Ok(())
+}
+
+/// Follow WM_STATE changes made by the window manager or the application.
+///
+/// Iconified and withdrawn windows are never remapped by a switch. A
+/// window restored (from TWM's icon manager, for example) while its own
+/// desktop isn't shown moves to the current desktop, where the window
+/// manager displayed it.
+fn update_wm_states(state: &mut DesktopState, infos: &[WindowInfo]) {
+    for info in infos {
+        match info.wm_state {
+            Some(WmState::Iconic) => state.set_iconified(info.id, true),
+            Some(WmState::Withdrawn)
+                if !info.is_mapped && state.is_visible_on(info.id, state.current) =>
+            {
+                state.set_app_hidden(info.id, true);
+            }
+            Some(WmState::Normal) if info.is_mapped => {
+                let restored = state.is_iconified(info.id) || state.is_app_hidden(info.id);
+                state.set_iconified(info.id, false);
+                state.set_app_hidden(info.id, false);
+
+                if restored && !state.is_visible_on(info.id, state.current) {
+                    state.set_window_desktop(info.id, state.current + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
 /// Detect newly appeared windows and handle app-hidden state.
-fn detect_new_windows(state: &mut DesktopState, infos: &[crate::x11::WindowInfo]) {
+///
+/// New windows that ask to be sticky (_NET_WM_STATE_STICKY, docks) become
+/// sticky, and those that arrive with a _NET_WM_DESKTOP hint go to that desktop.
+/// Transients and group members join their leader's desktop. Otherwise
+/// those that match a remembered fingerprint go back to their
+/// previous desktop, and the rest are assigned to the current desktop.
+fn detect_new_windows(backend: &impl Backend, state: &mut DesktopState, infos: &[WindowInfo]) {
     for info in infos {
         let key = info.id.to_string();
         let is_new = !state.windows.contains_key(&key);
 
         if is_new {
-            // Assign new window to current desktop
-            state.get_window_desktop(info.id, state.current);
+            let fingerprint = backend.get_window_fingerprint(info.id).unwrap_or_default();
+            let hint = if ewmh::wants_sticky(backend, info.id) {
+                Some(0)
+            } else {
+                ewmh::window_desktop_hint(backend, info.id, state.desktops)
+            };
+            let leader_desktop = backend
+                .get_window_leader(info.id)
+                .unwrap_or(None)
+                .and_then(|leader| state.windows.get(&leader.to_string()).copied());
+            // Only windows placed by nothing else may use up a remembered
+            // desktop, which is meant for the application's main window
+            let desktop = match hint.or(leader_desktop) {
+                Some(desktop) => desktop,
+                None => state
+                    .take_remembered_desktop(&fingerprint)
+                    .unwrap_or(state.current + 1),
+            };
+            state.set_window_desktop(info.id, desktop);
+            state.set_fingerprint(info.id, fingerprint);
 
             // If already hidden on arrival, mark as app-hidden
             if !info.is_mapped {
                 state.set_app_hidden(info.id, true);
             }
+        } else if !state.has_fingerprint(info.id) {
+            // Windows tracked before fingerprints were recorded
+            if let Ok(fingerprint) = backend.get_window_fingerprint(info.id) {
+                state.set_fingerprint(info.id, fingerprint);
+            }
         }
     }
 }
 
 /// Update window visibility based on target desktop.
 fn update_window_visibility(
-    x11: &X11Connection,
+    backend: &impl Backend,
     state: &DesktopState,
-    infos: &[crate::x11::WindowInfo],
+    infos: &[WindowInfo],
     target: u32,
 ) -> Result<()> {
     for info in infos {
+        // Unmapping an iconified client would withdraw it
+        if state.is_iconified(info.id) {
+            continue;
+        }
         if state.is_visible_on(info.id, target) {
-            x11.map_window(info.id)?;
+            backend.map_window(info.id)?;
         } else {
-            x11.unmap_window(info.id)?;
+            backend.unmap_window(info.id)?;
         }
     }
     Ok(())
 }
 
 /// Save the current stacking order for a desktop.
-fn save_stacking_order(x11: &X11Connection, state: &mut DesktopState, desktop: u32) -> Result<()> {
-    let stacking = x11.get_stacking_order()?;
+fn save_stacking_order(backend: &impl Backend, state: &mut DesktopState, desktop: u32) -> Result<()> {
+    let stacking = backend.get_stacking_order()?;
 
     // Filter to only windows visible on this desktop
     let desktop_stacking: Vec<String> = stacking
@@ -84,10 +338,10 @@ fn save_stacking_order(x11: &X11Connection, state: &mut DesktopState, desktop: u
 }
 
 /// Restore the stacking order for a desktop.
-fn restore_stacking_order(x11: &X11Connection, state: &DesktopState, desktop: u32) -> Result<()> {
+fn restore_stacking_order(backend: &impl Backend, state: &DesktopState, desktop: u32) -> Result<()> {
     if let Some(order) = state.stacking.get(&desktop) {
         // Convert string IDs back to u32 and filter out any that no longer exist
-        let current_windows: std::collections::HashSet<u32> = x11
+        let current_windows: std::collections::HashSet<u32> = backend
             .get_stacking_order()?
             .into_iter()
             .collect();
@@ -98,32 +352,48 @@ fn restore_stacking_order(x11: &X11Connection, state: &DesktopState, desktop: u3
             .filter(|id| current_windows.contains(id))
             .collect();
 
-        x11.restack_windows(&order)?;
+        backend.restack_windows(&order)?;
     }
     Ok(())
 }
 
-/// Switch to the next desktop (wraps around).
-pub fn switch_next(x11: &X11Connection, state: &mut DesktopState) -> Result<u32> {
-    let next = (state.current + 1) % state.desktops;
-    switch_to_desktop(x11, state, next)?;
-    Ok(next)
+/// Switch to the next desktop, wrapping around to the first if `wrap`
+/// is set and otherwise staying on the last.
+pub fn switch_next(backend: &impl Backend, state: &mut DesktopState, wrap: bool) -> Result<u32> {
+    state.transaction(backend, |state| {
+        let next = if state.current + 1 < state.desktops {
+            state.current + 1
+        } else if wrap {
+            0
+        } else {
+            return Ok(state.current);
+        };
+        record_switch(state, next);
+        apply_switch(backend, state, next)?;
+        Ok(next)
+    })
 }
 
-/// Switch to the previous desktop (wraps around).
-pub fn switch_prev(x11: &X11Connection, state: &mut DesktopState) -> Result<u32> {
-    let prev = if state.current == 0 {
-        state.desktops - 1
-    } else {
-        state.current - 1
-    };
-    switch_to_desktop(x11, state, prev)?;
-    Ok(prev)
+/// Switch to the previous desktop, wrapping around to the last if `wrap`
+/// is set and otherwise staying on the first.
+pub fn switch_prev(backend: &impl Backend, state: &mut DesktopState, wrap: bool) -> Result<u32> {
+    state.transaction(backend, |state| {
+        let prev = if state.current > 0 {
+            state.current - 1
+        } else if wrap {
+            state.desktops - 1
+        } else {
+            return Ok(state.current);
+        };
+        record_switch(state, prev);
+        apply_switch(backend, state, prev)?;
+        Ok(prev)
+    })
 }
 
 /// Set the number of desktops, relocating windows if necessary.
 pub fn set_desktop_count(
-    x11: &X11Connection,
+    backend: &impl Backend,
     state: &mut DesktopState,
     count: u32,
 ) -> Result<()> {
@@ -131,6 +401,22 @@ pub fn set_desktop_count(
         return Err(anyhow!("Desktop count must be at least 1"));
     }
 
+    state.transaction(backend, |state| {
+        state.record_history(format!("set desktops to {}", count));
+        apply_desktop_count(backend, state, count)
+    })
+}
+
+/// Change the desktop count on already-locked state.
+pub(crate) fn apply_desktop_count(backend: &impl Backend, state: &mut DesktopState, count: u32) -> Result<()> {
+    if backend.has_ewmh_desktops()? {
+        // The window manager relocates windows from removed desktops
+        ewmh::request_desktop_count(backend, count)?;
+        state.desktops = count;
+        state.current = state.current.min(count - 1);
+        return Ok(());
+    }
+
     // Move windows from removed desktops to the last valid one
     if count < state.desktops {
         for win_desktop in state.windows.values_mut() {
@@ -145,25 +431,392 @@ pub fn set_desktop_count(
     // Switch to last valid desktop if current is now invalid
     if state.current >= count {
         let new_current = count - 1;
-        switch_to_desktop(x11, state, new_current)?;
+        apply_switch(backend, state, new_current)?;
     }
 
-    state.sync_to_x(x11)?;
-    state.save()?;
-
     Ok(())
 }
 
+/// Name a desktop (1-indexed). An empty name removes it.
+pub fn rename_desktop(
+    backend: &impl Backend,
+    state: &mut DesktopState,
+    desktop: u32,
+    name: &str,
+) -> Result<()> {
+    if name.parse::<u32>().is_ok() {
+        return Err(anyhow!("Desktop names cannot be numbers"));
+    }
+
+    state.transaction(backend, |state| {
+        if desktop == 0 || desktop > state.desktops {
+            return Err(anyhow!(
+                "Invalid desktop {}. Valid range: 1-{}",
+                desktop,
+                state.desktops
+            ));
+        }
+
+        state.set_desktop_name(desktop - 1, name);
+        Ok(())
+    })
+}
+
 /// List all desktops with current marker.
 pub fn list_desktops(state: &DesktopState) {
-    println!("Desktops: {} (current: {})", state.desktops, state.current + 1);
+    print!("{}", format_desktops(state));
+}
+
+/// Format the desktop list printed by `list_desktops`.
+pub fn format_desktops(state: &DesktopState) -> String {
+    let mut out = format!("Desktops: {} (current: {})\n", state.desktops, state.current + 1);
     for i in 0..state.desktops {
+        let name = state.desktop_name(i).map(|n| format!("  {}", n)).unwrap_or_default();
         let marker = if i == state.current { " *" } else { "" };
-        println!("  {}{}", i + 1, marker);
+        out.push_str(&format!("  {}{}{}\n", i + 1, name, marker));
     }
+    out
 }
 
 /// Print the current desktop number (1-indexed).
 pub fn print_current_desktop(state: &DesktopState) {
     println!("{}", state.current + 1);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::fake::{Call, FakeBackend, FakeWindow};
+    use crate::fingerprint::{RememberedWindow, WindowFingerprint};
+
+    fn setup(windows: &[u32]) -> (FakeBackend, DesktopState) {
+        let backend = FakeBackend::new();
+        for &id in windows {
+            backend.add_window(id, &format!("window {}", id));
+        }
+        let state = DesktopState {
+            desktops: 4,
+            ..Default::default()
+        };
+        (backend, state)
+    }
+
+    #[test]
+    fn switch_shows_only_target_desktop_windows() {
+        let (backend, mut state) = setup(&[1, 2, 3]);
+        state.set_window_desktop(1, 1);
+        state.set_window_desktop(2, 2);
+        state.set_window_desktop(3, 0);
+
+        apply_switch(&backend, &mut state, 1).unwrap();
+
+        assert_eq!(state.current, 1);
+        assert!(!backend.is_mapped(1));
+        assert!(backend.is_mapped(2));
+        assert!(backend.is_mapped(3), "sticky windows stay visible");
+    }
+
+    #[test]
+    fn new_windows_join_current_desktop() {
+        let (backend, mut state) = setup(&[1]);
+        state.current = 2;
+
+        apply_switch(&backend, &mut state, 2).unwrap();
+
+        assert_eq!(state.windows.get("1"), Some(&3));
+    }
+
+    #[test]
+    fn new_windows_honor_net_wm_desktop() {
+        let (backend, mut state) = setup(&[1, 2, 3]);
+        backend.set_window_property(1, ewmh::NET_WM_DESKTOP, 3).unwrap();
+        backend.set_window_property(2, ewmh::NET_WM_DESKTOP, ewmh::ALL_DESKTOPS).unwrap();
+        backend.set_window_property(3, ewmh::NET_WM_DESKTOP, 9).unwrap();
+
+        apply_switch(&backend, &mut state, 0).unwrap();
+
+        assert_eq!(state.windows.get("1"), Some(&4));
+        assert_eq!(state.windows.get("2"), Some(&0));
+        assert_eq!(state.windows.get("3"), Some(&1), "out of range hints are ignored");
+        assert!(!backend.is_mapped(1));
+    }
+
+    #[test]
+    fn new_transients_join_their_leader() {
+        let (backend, mut state) = setup(&[1]);
+        state.set_window_desktop(1, 3);
+        backend.add_fake_window(FakeWindow {
+            id: 2,
+            mapped: true,
+            leader: Some(1),
+            ..Default::default()
+        });
+
+        apply_switch(&backend, &mut state, 0).unwrap();
+
+        assert_eq!(state.windows.get("2"), Some(&3));
+        assert!(!backend.is_mapped(2));
+    }
+
+    #[test]
+    fn dialogs_leave_the_remembered_desktop_to_the_main_window() {
+        let (backend, mut state) = setup(&[1]);
+        state.set_window_desktop(1, 2);
+        let fingerprint = WindowFingerprint {
+            class: vec!["editor".to_string(), "Editor".to_string()],
+            ..Default::default()
+        };
+        state.remembered.push(RememberedWindow {
+            fingerprint: fingerprint.clone(),
+            desktop: 3,
+        });
+        backend.add_fake_window(FakeWindow {
+            id: 2,
+            mapped: true,
+            fingerprint: fingerprint.clone(),
+            leader: Some(1),
+            ..Default::default()
+        });
+        backend.add_fake_window(FakeWindow {
+            id: 3,
+            mapped: true,
+            fingerprint,
+            ..Default::default()
+        });
+
+        apply_switch(&backend, &mut state, 0).unwrap();
+
+        assert_eq!(state.windows.get("2"), Some(&2), "follows its leader");
+        assert_eq!(state.windows.get("3"), Some(&3), "remembered desktop");
+    }
+
+    #[test]
+    fn new_sticky_and_dock_windows_become_sticky() {
+        let (backend, mut state) = setup(&[1, 2]);
+        let sticky = backend.intern_atom(ewmh::NET_WM_STATE_STICKY).unwrap();
+        let dock = backend.intern_atom(ewmh::NET_WM_WINDOW_TYPE_DOCK).unwrap();
+        backend.set_window_atoms(1, ewmh::NET_WM_STATE, &[sticky]).unwrap();
+        backend.set_window_atoms(2, ewmh::NET_WM_WINDOW_TYPE, &[dock]).unwrap();
+        backend.set_window_property(2, ewmh::NET_WM_DESKTOP, 3).unwrap();
+
+        apply_switch(&backend, &mut state, 0).unwrap();
+
+        assert_eq!(state.windows.get("1"), Some(&0));
+        assert_eq!(state.windows.get("2"), Some(&0));
+    }
+
+    #[test]
+    fn ewmh_window_manager_switches_desktops_itself() {
+        let (backend, mut state) = setup(&[1, 2]);
+        backend.set_ewmh_desktops(true);
+        state.set_window_desktop(1, 1);
+        state.set_window_desktop(2, 2);
+
+        apply_switch(&backend, &mut state, 1).unwrap();
+        apply_desktop_count(&backend, &mut state, 1).unwrap();
+
+        assert_eq!((state.current, state.desktops), (0, 1));
+        assert_eq!(
+            backend.calls(),
+            vec![
+                Call::ClientMessage(None, "_NET_CURRENT_DESKTOP".to_string(), [1, 0, 0, 0, 0]),
+                Call::ClientMessage(None, "_NET_NUMBER_OF_DESKTOPS".to_string(), [1, 0, 0, 0, 0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn windows_hidden_on_arrival_are_never_mapped() {
+        let (backend, mut state) = setup(&[1]);
+        backend.unmap_window(1).unwrap();
+        backend.clear_calls();
+
+        apply_switch(&backend, &mut state, 0).unwrap();
+
+        assert!(state.is_app_hidden(1));
+        assert!(!backend.calls().contains(&Call::Map(1)));
+    }
+
+    #[test]
+    fn iconified_windows_stay_hidden_until_restored() {
+        let (backend, mut state) = setup(&[1]);
+        apply_switch(&backend, &mut state, 0).unwrap();
+
+        // Iconified in the window manager
+        backend.unmap_window(1).unwrap();
+        backend.set_wm_state(1, Some(WmState::Iconic));
+        apply_switch(&backend, &mut state, 1).unwrap();
+        backend.clear_calls();
+        apply_switch(&backend, &mut state, 0).unwrap();
+
+        assert!(state.is_iconified(1));
+        assert!(!backend.calls().contains(&Call::Map(1)));
+
+        // Restored from the icon manager while desktop 2 is shown
+        apply_switch(&backend, &mut state, 1).unwrap();
+        backend.map_window(1).unwrap();
+        backend.set_wm_state(1, Some(WmState::Normal));
+        apply_switch(&backend, &mut state, 1).unwrap();
+
+        assert!(!state.is_iconified(1));
+        assert_eq!(state.windows.get("1"), Some(&2));
+        assert!(backend.is_mapped(1));
+    }
+
+    #[test]
+    fn show_desktop_hides_and_restores_exactly_the_shown_windows() {
+        let (backend, mut state) = setup(&[1, 2, 3, 4]);
+        apply_switch(&backend, &mut state, 0).unwrap();
+        state.set_window_desktop(2, 0);
+        state.set_window_desktop(3, 2);
+        backend.unmap_window(3).unwrap();
+        backend.unmap_window(4).unwrap();
+        state.set_app_hidden(4, true);
+        backend.restack_windows(&[2, 1]).unwrap();
+
+        apply_show_desktop(&backend, &mut state, true).unwrap();
+        assert!(state.showing_desktop);
+        assert!(!backend.is_mapped(1));
+        assert!(!backend.is_mapped(2), "sticky windows are hidden too");
+
+        backend.restack_windows(&[1, 2]).unwrap();
+        apply_show_desktop(&backend, &mut state, false).unwrap();
+        assert!(!state.showing_desktop);
+        assert!(backend.is_mapped(1) && backend.is_mapped(2));
+        assert!(!backend.is_mapped(3) && !backend.is_mapped(4));
+        assert_eq!(
+            backend.get_stacking_order().unwrap()[..2],
+            [2, 1],
+            "stacking order from before show-desktop is restored"
+        );
+    }
+
+    #[test]
+    fn switching_ends_show_desktop() {
+        let (backend, mut state) = setup(&[1, 2]);
+        apply_switch(&backend, &mut state, 0).unwrap();
+        state.set_window_desktop(2, 0);
+
+        apply_show_desktop(&backend, &mut state, true).unwrap();
+        apply_switch(&backend, &mut state, 1).unwrap();
+
+        assert!(!state.showing_desktop);
+        assert!(state.desktop_hidden.is_empty());
+        assert!(!backend.is_mapped(1));
+        assert!(backend.is_mapped(2));
+    }
+
+    #[test]
+    fn focus_is_remembered_per_desktop() {
+        let (backend, mut state) = setup(&[1, 2, 3]);
+        apply_switch(&backend, &mut state, 0).unwrap();
+        state.set_window_desktop(3, 2);
+        backend.restack_windows(&[2, 1]).unwrap();
+
+        backend.set_active_window(Some(2));
+        apply_switch(&backend, &mut state, 1).unwrap();
+        assert_eq!(backend.get_active_window().unwrap(), 3, "only window on desktop 2");
+
+        backend.set_active_window(Some(3));
+        apply_switch(&backend, &mut state, 0).unwrap();
+        assert_eq!(backend.get_active_window().unwrap(), 2, "not the topmost window 1");
+    }
+
+    #[test]
+    fn an_empty_desktop_focuses_the_root() {
+        let (backend, mut state) = setup(&[1]);
+        apply_switch(&backend, &mut state, 0).unwrap();
+        backend.set_active_window(Some(1));
+        backend.clear_calls();
+
+        apply_switch(&backend, &mut state, 2).unwrap();
+
+        assert!(backend.calls().contains(&Call::FocusRoot));
+        assert!(!backend.calls().iter().any(|call| matches!(call, Call::Focus(_))));
+    }
+
+    #[test]
+    fn focus_seen_by_the_daemon_is_recorded_and_published() {
+        let (backend, mut state) = setup(&[1, 2]);
+        apply_switch(&backend, &mut state, 0).unwrap();
+
+        record_focus(&backend, &mut state, 1).unwrap();
+
+        assert_eq!(state.remembered_focus(0), Some(1));
+        assert_eq!(backend.root_windows("_NET_ACTIVE_WINDOW"), Some(vec![1]));
+    }
+
+    #[test]
+    fn stacking_order_is_restored_per_desktop() {
+        let (backend, mut state) = setup(&[1, 2, 3]);
+        apply_switch(&backend, &mut state, 0).unwrap();
+
+        // User raises window 1 above the others on desktop 1
+        backend.restack_windows(&[2, 3, 1]).unwrap();
+
+        apply_switch(&backend, &mut state, 1).unwrap();
+        backend.restack_windows(&[1, 2, 3]).unwrap();
+        apply_switch(&backend, &mut state, 0).unwrap();
+
+        assert_eq!(backend.get_stacking_order().unwrap(), vec![2, 3, 1]);
+    }
+
+    #[test]
+    fn new_windows_are_tracked_as_they_map() {
+        let (backend, mut state) = setup(&[1, 2]);
+        apply_switch(&backend, &mut state, 0).unwrap();
+        backend.set_window_property(2, ewmh::NET_WM_DESKTOP, 2).unwrap();
+        backend.add_window(3, "hinted");
+        backend.set_window_property(3, ewmh::NET_WM_DESKTOP, 2).unwrap();
+        backend.add_fake_window(FakeWindow {
+            id: 4,
+            ..Default::default()
+        });
+        backend.destroy_window(1);
+
+        apply_track_new_windows(&backend, &mut state).unwrap();
+
+        assert!(!state.windows.contains_key("1"));
+        assert_eq!(state.windows.get("2"), Some(&1), "already tracked");
+        assert_eq!(state.windows.get("3"), Some(&3));
+        assert!(!backend.is_mapped(3), "belongs to another desktop");
+        assert!(!state.windows.contains_key("4"), "assigned once it maps");
+
+        backend.map_window(4).unwrap();
+        apply_track_new_windows(&backend, &mut state).unwrap();
+        assert_eq!(state.windows.get("4"), Some(&1));
+        assert!(backend.is_mapped(4));
+    }
+
+    #[test]
+    fn shrinking_folds_windows_onto_last_desktop() {
+        let (backend, mut state) = setup(&[1, 2, 3]);
+        state.set_window_desktop(1, 1);
+        state.set_window_desktop(2, 3);
+        state.set_window_desktop(3, 4);
+        state.current = 3;
+
+        apply_desktop_count(&backend, &mut state, 2).unwrap();
+
+        assert_eq!(state.desktops, 2);
+        assert_eq!(state.current, 1);
+        assert_eq!(state.windows.get("1"), Some(&1));
+        assert_eq!(state.windows.get("2"), Some(&2));
+        assert_eq!(state.windows.get("3"), Some(&2));
+        assert!(!backend.is_mapped(1));
+        assert!(backend.is_mapped(2));
+        assert!(backend.is_mapped(3));
+    }
+
+    #[test]
+    fn destroyed_windows_are_forgotten() {
+        let (backend, mut state) = setup(&[1, 2]);
+        apply_switch(&backend, &mut state, 0).unwrap();
+
+        backend.destroy_window(2);
+        apply_switch(&backend, &mut state, 1).unwrap();
+
+        assert!(state.windows.contains_key("1"));
+        assert!(!state.windows.contains_key("2"));
+    }
+}
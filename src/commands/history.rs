@@ -0,0 +1,136 @@
+use anyhow::{anyhow, Result};
+
+use super::desktop::{apply_desktop_count, apply_switch};
+use crate::backend::Backend;
+use crate::ewmh;
+use crate::history::HistoryEntry;
+use crate::state::DesktopState;
+
+/// Undo the most recent desktop or window operation.
+///
+/// Returns a description of the operation that was undone.
+pub fn undo(backend: &impl Backend, state: &mut DesktopState) -> Result<String> {
+    state.transaction(backend, |state| apply_undo(backend, state))
+}
+
+/// Undo on already-locked state.
+fn apply_undo(backend: &impl Backend, state: &mut DesktopState) -> Result<String> {
+    let entry = state.undo.pop().ok_or_else(|| anyhow!("Nothing to undo"))?;
+
+    let replaced = state.snapshot(entry.operation.clone());
+    state.redo.push(replaced);
+
+    restore(backend, state, &entry)?;
+    Ok(entry.operation)
+}
+
+/// Redo the most recently undone operation.
+///
+/// Returns a description of the operation that was redone.
+pub fn redo(backend: &impl Backend, state: &mut DesktopState) -> Result<String> {
+    state.transaction(backend, |state| apply_redo(backend, state))
+}
+
+/// Redo on already-locked state.
+fn apply_redo(backend: &impl Backend, state: &mut DesktopState) -> Result<String> {
+    let entry = state.redo.pop().ok_or_else(|| anyhow!("Nothing to redo"))?;
+
+    let replaced = state.snapshot(entry.operation.clone());
+    state.undo.push(replaced);
+
+    restore(backend, state, &entry)?;
+    Ok(entry.operation)
+}
+
+/// Apply a snapshot and bring window visibility in line with it.
+///
+/// Under an EWMH window manager the desktop count and moved windows are
+/// requested from it like `set-desktops` and `move` do, since it owns them
+/// and would otherwise overwrite the restored state on the next sync.
+fn restore(backend: &impl Backend, state: &mut DesktopState, entry: &HistoryEntry) -> Result<()> {
+    let resized = entry.desktops != state.desktops;
+    let moved = state.restore_snapshot(entry);
+
+    // After the snapshot, so windows on removed desktops join the restored
+    // current desktop rather than the last one
+    if resized {
+        apply_desktop_count(backend, state, entry.desktops)?;
+    }
+    if backend.has_ewmh_desktops()? {
+        for (window, desktop) in moved {
+            ewmh::request_move(backend, window, desktop)?;
+        }
+    }
+
+    apply_switch(backend, state, entry.current.min(entry.desktops - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::fake::{Call, FakeBackend};
+
+    #[test]
+    fn undo_brings_windows_from_removed_desktops_into_view() {
+        let backend = FakeBackend::new();
+        backend.add_window(1, "editor");
+        let mut state = DesktopState {
+            desktops: 2,
+            ..Default::default()
+        };
+        apply_switch(&backend, &mut state, 0).unwrap();
+
+        state.record_history("set desktops to 4".to_string());
+        apply_desktop_count(&backend, &mut state, 4).unwrap();
+        apply_switch(&backend, &mut state, 3).unwrap();
+        backend.add_window(2, "opened on desktop 4");
+        apply_switch(&backend, &mut state, 3).unwrap();
+        assert_eq!(state.windows.get("2"), Some(&4));
+
+        assert_eq!(apply_undo(&backend, &mut state).unwrap(), "set desktops to 4");
+        assert_eq!((state.desktops, state.current), (2, 0));
+        assert_eq!(state.windows.get("2"), Some(&1));
+        assert!(backend.is_mapped(1) && backend.is_mapped(2));
+
+        assert_eq!(apply_redo(&backend, &mut state).unwrap(), "set desktops to 4");
+        assert_eq!((state.desktops, state.current), (4, 3));
+        assert!(!backend.is_mapped(1));
+        assert!(apply_redo(&backend, &mut state).is_err());
+    }
+
+    #[test]
+    fn undo_is_requested_from_an_ewmh_window_manager() {
+        let backend = FakeBackend::new();
+        backend.add_window(1, "editor");
+        backend.add_window(2, "shell");
+        backend.set_ewmh_desktops(true);
+        let mut state = DesktopState {
+            desktops: 4,
+            ..Default::default()
+        };
+        state.set_window_desktop(1, 1);
+        state.set_window_desktop(2, 2);
+
+        state.record_history("set desktops to 6".to_string());
+        state.desktops = 6;
+        state.set_window_desktop(1, 6);
+
+        assert_eq!(apply_undo(&backend, &mut state).unwrap(), "set desktops to 6");
+        assert_eq!(state.windows.get("1"), Some(&1));
+        assert_eq!(
+            backend.calls(),
+            vec![
+                Call::ClientMessage(None, "_NET_NUMBER_OF_DESKTOPS".to_string(), [4, 0, 0, 0, 0]),
+                Call::ClientMessage(Some(1), "_NET_WM_DESKTOP".to_string(), [0, 2, 0, 0, 0]),
+                Call::ClientMessage(None, "_NET_CURRENT_DESKTOP".to_string(), [0, 0, 0, 0, 0]),
+            ]
+        );
+
+        backend.clear_calls();
+        assert_eq!(apply_redo(&backend, &mut state).unwrap(), "set desktops to 6");
+        assert_eq!((state.desktops, state.windows.get("1")), (6, Some(&6)));
+        assert!(backend
+            .calls()
+            .contains(&Call::ClientMessage(Some(1), "_NET_WM_DESKTOP".to_string(), [5, 2, 0, 0, 0])));
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+
+use super::desktop::track_windows;
+use super::window::apply_move;
+use crate::backend::Backend;
+use crate::fingerprint::WindowFingerprint;
+use crate::session::{Session, SessionApp, SessionWindow};
+use crate::state::DesktopState;
+use crate::x11::X11Connection;
+
+/// How often to look for windows of relaunched applications.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Save every window's launch command, desktop and geometry under a name.
+///
+/// The command comes from WM_COMMAND, or from `/proc/<pid>/cmdline` via
+/// _NET_WM_PID. Windows belonging to the same process are grouped so the
+/// application is launched only once on restore.
+pub fn save_session(x11: &X11Connection, state: &mut DesktopState, name: &str) -> Result<()> {
+    let infos = x11.get_all_window_info()?;
+
+    // Make sure every window has an assignment before recording it
+    state.transaction(x11, |state| {
+        track_windows(x11, state, &infos);
+        Ok(())
+    })?;
+
+    let mut session = Session::default();
+    let mut app_by_pid: HashMap<u32, usize> = HashMap::new();
+
+    for info in &infos {
+        let Some(&desktop) = state.windows.get(&info.id.to_string()) else {
+            continue;
+        };
+
+        // Windows may close while we query them; skip those
+        let (client, window) = match describe_window(x11, info.id, desktop) {
+            Ok(described) => described,
+            Err(e) => {
+                eprintln!("xdeskie: skipping 0x{:x} ({}): {}", info.id, info.name, e);
+                continue;
+            }
+        };
+        let pid = x11.get_window_pid(client).unwrap_or(None);
+        let Some(command) = launch_command(x11, client, pid) else {
+            eprintln!("xdeskie: skipping 0x{:x} ({}): no launch command", info.id, info.name);
+            continue;
+        };
+
+        match pid.and_then(|pid| app_by_pid.get(&pid)) {
+            Some(&index) => session.apps[index].windows.push(window),
+            None => {
+                if let Some(pid) = pid {
+                    app_by_pid.insert(pid, session.apps.len());
+                }
+                session.apps.push(SessionApp {
+                    command,
+                    windows: vec![window],
+                });
+            }
+        }
+    }
+
+    session.save(name)?;
+    println!("Saved session '{}' ({} applications)", name, session.apps.len());
+
+    Ok(())
+}
+
+/// Find a window's client and record where the window is, so its
+/// relaunched counterpart can be placed there.
+fn describe_window(x11: &X11Connection, window: u32, desktop: u32) -> Result<(u32, SessionWindow)> {
+    let client = x11.client_window(window)?;
+    let saved = SessionWindow {
+        fingerprint: WindowFingerprint::from_window(x11, window)?,
+        desktop,
+        geometry: x11.get_window_geometry(window)?,
+    };
+    Ok((client, saved))
+}
+
+/// Relaunch a saved session and place each new window where it was.
+///
+/// Waits up to `timeout` for launched windows to appear. New windows are
+/// matched to saved ones by process ID first and fingerprint second, and
+/// placed together once all have appeared or the time is up, so a single
+/// undo takes the whole restore back.
+pub fn restore_session(
+    x11: &X11Connection,
+    state: &mut DesktopState,
+    name: &str,
+    timeout: Duration,
+) -> Result<()> {
+    let session = Session::load(name)?;
+
+    // Windows that exist before launching can't belong to the session
+    let mut known: HashSet<u32> = x11.get_toplevel_windows()?.into_iter().collect();
+    let mut pending: Vec<(u32, &SessionWindow)> = Vec::new();
+    let mut placed: Vec<(u32, &SessionWindow)> = Vec::new();
+
+    for app in &session.apps {
+        match launch(&app.command) {
+            Ok(pid) => pending.extend(app.windows.iter().map(|w| (pid, w))),
+            Err(e) => eprintln!("xdeskie: failed to launch '{}': {}", app.command.join(" "), e),
+        }
+    }
+
+    let deadline = Instant::now() + timeout;
+    while !pending.is_empty() && Instant::now() < deadline {
+        thread::sleep(POLL_INTERVAL);
+
+        for window in x11.get_toplevel_windows()? {
+            if !known.insert(window) {
+                continue;
+            }
+            if let Some(index) = match_pending(x11, window, &pending) {
+                let (_, placement) = pending.remove(index);
+                placed.push((window, placement));
+            }
+        }
+    }
+
+    place_windows(x11, state, name, &placed)?;
+
+    if !pending.is_empty() {
+        eprintln!(
+            "xdeskie: {} windows did not appear within {}s",
+            pending.len(),
+            timeout.as_secs()
+        );
+    }
+    println!("Restored session '{}'", name);
+
+    Ok(())
+}
+
+/// Get the command line that launched a client window.
+fn launch_command(x11: &X11Connection, client: u32, pid: Option<u32>) -> Option<Vec<String>> {
+    let command = x11.get_window_command(client).unwrap_or_default();
+    if !command.is_empty() {
+        return Some(command);
+    }
+
+    let raw = fs::read(format!("/proc/{}/cmdline", pid?)).ok()?;
+    let command: Vec<String> = raw
+        .split(|&b| b == 0)
+        .filter(|s| !s.is_empty())
+        .map(|s| String::from_utf8_lossy(s).to_string())
+        .collect();
+
+    (!command.is_empty()).then_some(command)
+}
+
+/// Start an application detached from our terminal, returning its PID.
+fn launch(command: &[String]) -> Result<u32> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| anyhow!("empty command"))?;
+
+    let child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()?;
+
+    Ok(child.id())
+}
+
+/// Pick the pending saved window that best matches a new window.
+fn match_pending(x11: &X11Connection, window: u32, pending: &[(u32, &SessionWindow)]) -> Option<usize> {
+    let client = x11.client_window(window).ok()?;
+    let pid = x11.get_window_pid(client).unwrap_or(None);
+    let fingerprint = WindowFingerprint::from_window(x11, window).ok()?;
+
+    pending
+        .iter()
+        .enumerate()
+        .filter_map(|(index, (launched_pid, saved))| {
+            let score = saved.fingerprint.match_score(&fingerprint)?;
+            Some((index, (pid == Some(*launched_pid), score)))
+        })
+        .max_by_key(|&(_, key)| key)
+        .map(|(index, _)| index)
+}
+
+/// Move relaunched windows to their saved desktops and geometry in one
+/// transaction. A window that can't be placed, such as a splash screen that
+/// has already closed, is reported and skipped.
+fn place_windows(
+    x11: &X11Connection,
+    state: &mut DesktopState,
+    name: &str,
+    placed: &[(u32, &SessionWindow)],
+) -> Result<()> {
+    if placed.is_empty() {
+        return Ok(());
+    }
+
+    state.transaction(x11, |state| {
+        state.record_history(format!("restore session '{}'", name));
+        for &(window, placement) in placed {
+            if let Err(e) = place_window(x11, state, window, placement) {
+                eprintln!("xdeskie: could not place 0x{:x}: {}", window, e);
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Move a relaunched window to its saved desktop and geometry.
+fn place_window(
+    x11: &X11Connection,
+    state: &mut DesktopState,
+    window: u32,
+    placement: &SessionWindow,
+) -> Result<()> {
+    let desktop = placement.desktop.min(state.desktops);
+    apply_move(x11, state, window, desktop)?;
+    x11.move_resize_window(window, &placement.geometry)?;
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use anyhow::Result;
+
+use crate::state::DesktopState;
+use crate::x11::X11Connection;
+
+/// List saved states for all displays, marking ones whose X server is gone
+/// or can't be reached.
+pub fn list_states() -> Result<()> {
+    let states = DesktopState::saved_states()?;
+
+    if states.is_empty() {
+        println!("No saved states");
+        return Ok(());
+    }
+
+    println!("Saved states:");
+    for saved in &states {
+        let status = if X11Connection::display_exists(&saved.display) {
+            ""
+        } else if saved.socket_is_gone() {
+            " [gone]"
+        } else {
+            " [unreachable]"
+        };
+        println!(
+            "  {}.{}  {}{}",
+            saved.display,
+            saved.screen,
+            saved.path.display(),
+            status
+        );
+    }
+
+    Ok(())
+}
+
+/// Remove saved states for local displays that no longer exist.
+///
+/// Displays that merely refuse the connection, or are remote, are listed
+/// and kept.
+pub fn prune_states() -> Result<()> {
+    let mut removed = 0;
+
+    for saved in DesktopState::saved_states()? {
+        if X11Connection::display_exists(&saved.display) {
+            continue;
+        }
+        if !saved.socket_is_gone() {
+            println!("Kept state for {}.{} (unreachable, not gone)", saved.display, saved.screen);
+            continue;
+        }
+        saved.remove()?;
+        println!("Removed state for {}.{}", saved.display, saved.screen);
+        removed += 1;
+    }
+
+    if removed == 0 {
+        println!("No stale states found");
+    }
+
+    Ok(())
+}
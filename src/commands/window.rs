@@ -1,7 +1,12 @@
+use std::collections::{HashMap, HashSet};
+
 use anyhow::{anyhow, Result};
 
+use super::desktop::track_windows;
+use crate::backend::Backend;
+use crate::ewmh;
 use crate::state::DesktopState;
-use crate::x11::X11Connection;
+use crate::x11::WindowInfo;
 
 /// Parse a window ID from string.
 ///
@@ -9,9 +14,9 @@ use crate::x11::X11Connection;
 /// - "active" - returns the currently focused window
 /// - "0x1234" - hexadecimal window ID
 /// - "1234" - decimal window ID
-pub fn parse_window_id(s: &str, x11: &X11Connection) -> Result<u32> {
+pub fn parse_window_id(s: &str, backend: &impl Backend) -> Result<u32> {
     if s.eq_ignore_ascii_case("active") {
-        return x11.get_active_window();
+        return backend.get_active_window();
     }
 
     let id = if s.starts_with("0x") || s.starts_with("0X") {
@@ -26,12 +31,26 @@ pub fn parse_window_id(s: &str, x11: &X11Connection) -> Result<u32> {
 /// Move a window to a specific desktop.
 ///
 /// Desktop 0 makes the window sticky (visible on all desktops).
+/// `window_id` may be a toplevel frame or any window inside it, such as
+/// the client window or the focused window.
 pub fn move_window(
-    x11: &X11Connection,
+    backend: &impl Backend,
+    state: &mut DesktopState,
+    window_id: u32,
+    desktop: u32,
+) -> Result<()> {
+    state.transaction(backend, |state| apply_move_window(backend, state, window_id, desktop))
+}
+
+/// Validate and record a move on already-locked state, then perform it.
+pub(crate) fn apply_move_window(
+    backend: &impl Backend,
     state: &mut DesktopState,
     window_id: u32,
     desktop: u32,
 ) -> Result<()> {
+    let window_id = backend.toplevel_window(window_id)?;
+
     if desktop > state.desktops {
         return Err(anyhow!(
             "Invalid desktop {}. Valid range: 0-{} (0=sticky)",
@@ -40,57 +59,141 @@ pub fn move_window(
         ));
     }
 
-    state.set_window_desktop(window_id, desktop);
+    state.record_history(if desktop == 0 {
+        format!("make window 0x{:x} sticky", window_id)
+    } else {
+        format!("move window 0x{:x} to desktop {}", window_id, desktop)
+    });
+
+    apply_move(backend, state, window_id, desktop)
+}
+
+/// Move a window and its followers on already-locked state.
+pub(crate) fn apply_move(backend: &impl Backend, state: &mut DesktopState, window_id: u32, desktop: u32) -> Result<()> {
     state.set_app_hidden(window_id, false);
+    state.set_desktop_hidden(window_id, false);
 
-    // Update visibility: show if sticky or on current desktop
-    let should_show = desktop == 0 || desktop == state.current + 1;
-    if should_show {
-        x11.map_window(window_id)?;
-    } else {
-        x11.unmap_window(window_id)?;
-    }
+    let leaders = window_leaders(backend, state)?;
+    let mut windows = vec![window_id];
+    windows.extend(followers(&leaders, window_id));
+
+    let delegate = backend.has_ewmh_desktops()?;
+    for window in windows {
+        state.set_window_desktop(window, desktop);
 
-    state.save()?;
+        if delegate {
+            ewmh::request_move(backend, window, desktop)?;
+        } else if state.is_iconified(window) {
+            // Iconified windows keep their icon until the user restores them
+        } else if state.is_visible_on(window, state.current) {
+            backend.map_window(window)?;
+        } else {
+            backend.unmap_window(window)?;
+        }
+    }
 
     Ok(())
 }
 
-/// List all windows and their desktop assignments.
-pub fn list_windows(x11: &X11Connection, state: &mut DesktopState) -> Result<()> {
-    let infos = x11.get_all_window_info()?;
+/// Map each tracked window to the tracked window it follows between
+/// desktops (see `Backend::get_window_leader`).
+pub fn window_leaders(backend: &impl Backend, state: &DesktopState) -> Result<HashMap<u32, u32>> {
+    let mut leaders = HashMap::new();
 
-    // Ensure all windows are tracked and detect app-hidden
-    for info in &infos {
-        let key = info.id.to_string();
-        let is_new = !state.windows.contains_key(&key);
-        state.get_window_desktop(info.id, state.current);
+    for window in backend.get_toplevel_windows()? {
+        if !state.windows.contains_key(&window.to_string()) {
+            continue;
+        }
+        // Group leaders are often unmapped placeholder windows; only
+        // windows xdeskie tracks can lead
+        if let Ok(Some(leader)) = backend.get_window_leader(window) {
+            if state.windows.contains_key(&leader.to_string()) {
+                leaders.insert(window, leader);
+            }
+        }
+    }
 
-        if is_new && !info.is_mapped {
-            state.set_app_hidden(info.id, true);
+    Ok(leaders)
+}
+
+/// Find the windows that follow a window, directly or through other
+/// followers: its transients and, for a group leader, the group members.
+pub fn followers(leaders: &HashMap<u32, u32>, window: u32) -> Vec<u32> {
+    let mut result = Vec::new();
+    let mut queue = vec![window];
+
+    while let Some(leader) = queue.pop() {
+        for (&follower, &l) in leaders {
+            if l == leader && follower != window && !result.contains(&follower) {
+                result.push(follower);
+                queue.push(follower);
+            }
         }
     }
 
-    let window_ids: Vec<u32> = infos.iter().map(|i| i.id).collect();
-    state.cleanup_dead_windows(&window_ids);
-    state.save()?;
+    result.sort_unstable();
+    result
+}
+
+/// List all windows and their desktop assignments.
+pub fn list_windows(backend: &impl Backend, state: &mut DesktopState) -> Result<()> {
+    let infos = backend.get_all_window_info()?;
+
+    // Ensure all windows are tracked and detect app-hidden
+    state.transaction(backend, |state| {
+        track_windows(backend, state, &infos);
+        Ok(())
+    })?;
 
     println!("Windows (current desktop: {}):", state.current + 1);
 
+    // Followers are listed indented under the window they follow
+    let leaders = window_leaders(backend, state)?;
+    let mut printed = HashSet::new();
+    for info in infos.iter().filter(|info| !leaders.contains_key(&info.id)) {
+        print_window_tree(state, &infos, &leaders, info, 0, &mut printed);
+    }
+    // Windows in leader cycles have no root to be listed under
     for info in &infos {
-        let desktop = state.windows.get(&info.id.to_string()).copied().unwrap_or(0);
-        let desktop_str = format_desktop(desktop);
-        let status = format_window_status(state, info);
-
-        println!(
-            "  0x{:08x}  desktop {}  {:.40}{}",
-            info.id, desktop_str, info.name, status
-        );
+        if !printed.contains(&info.id) {
+            print_window_tree(state, &infos, &leaders, info, 0, &mut printed);
+        }
     }
 
     Ok(())
 }
 
+fn print_window_tree(
+    state: &DesktopState,
+    infos: &[WindowInfo],
+    leaders: &HashMap<u32, u32>,
+    info: &WindowInfo,
+    depth: usize,
+    printed: &mut HashSet<u32>,
+) {
+    if !printed.insert(info.id) {
+        return;
+    }
+
+    let desktop = state.windows.get(&info.id.to_string()).copied().unwrap_or(0);
+    let desktop_str = format_desktop(desktop);
+    let status = format_window_status(state, info);
+
+    println!(
+        "  {:indent$}0x{:08x}  desktop {}  {:.40}{}",
+        "",
+        info.id,
+        desktop_str,
+        info.name,
+        status,
+        indent = depth * 2
+    );
+
+    for follower in infos.iter().filter(|f| leaders.get(&f.id) == Some(&info.id)) {
+        print_window_tree(state, infos, leaders, follower, depth + 1, printed);
+    }
+}
+
 fn format_desktop(desktop: u32) -> String {
     if desktop == 0 {
         "sticky".to_string()
@@ -99,8 +202,10 @@ fn format_desktop(desktop: u32) -> String {
     }
 }
 
-fn format_window_status(state: &DesktopState, info: &crate::x11::WindowInfo) -> &'static str {
-    if state.is_app_hidden(info.id) {
+fn format_window_status(state: &DesktopState, info: &WindowInfo) -> &'static str {
+    if state.is_iconified(info.id) {
+        " [iconified]"
+    } else if state.is_app_hidden(info.id) {
         " [app-hidden]"
     } else if !info.is_mapped {
         " [hidden]"
@@ -108,3 +213,97 @@ fn format_window_status(state: &DesktopState, info: &crate::x11::WindowInfo) ->
         ""
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::fake::{FakeBackend, FakeWindow};
+
+    #[test]
+    fn moving_away_hides_and_sticky_shows() {
+        let backend = FakeBackend::new();
+        backend.add_window(1, "editor");
+        let mut state = DesktopState {
+            desktops: 4,
+            ..Default::default()
+        };
+        state.set_window_desktop(1, 1);
+
+        apply_move(&backend, &mut state, 1, 3).unwrap();
+        assert_eq!(state.windows.get("1"), Some(&3));
+        assert!(!backend.is_mapped(1));
+
+        apply_move(&backend, &mut state, 1, 0).unwrap();
+        assert_eq!(state.windows.get("1"), Some(&0));
+        assert!(backend.is_mapped(1));
+    }
+
+    #[test]
+    fn transients_follow_their_leader() {
+        let backend = FakeBackend::new();
+        backend.add_window(1, "editor");
+        for (id, leader) in [(2, 1), (3, 2)] {
+            backend.add_fake_window(FakeWindow {
+                id,
+                mapped: true,
+                leader: Some(leader),
+                ..Default::default()
+            });
+        }
+        let mut state = DesktopState {
+            desktops: 4,
+            ..Default::default()
+        };
+        for id in 1..=3 {
+            state.set_window_desktop(id, 1);
+        }
+
+        apply_move(&backend, &mut state, 1, 2).unwrap();
+        assert_eq!(state.windows.get("3"), Some(&2));
+        assert!(!backend.is_mapped(3));
+
+        // Moving a follower leaves its leader in place
+        apply_move(&backend, &mut state, 3, 1).unwrap();
+        assert_eq!(state.windows.get("1"), Some(&2));
+        assert_eq!(state.windows.get("3"), Some(&1));
+    }
+
+    #[test]
+    fn finds_toplevel_from_client() {
+        let backend = FakeBackend::new();
+        backend.add_fake_window(FakeWindow {
+            id: 10,
+            mapped: true,
+            client: Some(11),
+            ..Default::default()
+        });
+
+        assert_eq!(backend.toplevel_window(10).unwrap(), 10);
+        assert_eq!(backend.toplevel_window(11).unwrap(), 10);
+        assert!(backend.toplevel_window(12).is_err());
+
+        let mut state = DesktopState {
+            desktops: 4,
+            ..Default::default()
+        };
+        state.set_window_desktop(10, 1);
+
+        apply_move_window(&backend, &mut state, 11, 3).unwrap();
+        assert_eq!(state.windows.get("10"), Some(&3));
+        assert!(!state.windows.contains_key("11"));
+        assert!(!backend.is_mapped(10));
+        assert_eq!(state.undo.last().unwrap().operation, "move window 0xa to desktop 3");
+        assert!(apply_move_window(&backend, &mut state, 12, 1).is_err());
+    }
+
+    #[test]
+    fn parses_window_ids() {
+        let backend = FakeBackend::new();
+        backend.set_active_window(Some(42));
+
+        assert_eq!(parse_window_id("0x1a", &backend).unwrap(), 26);
+        assert_eq!(parse_window_id("26", &backend).unwrap(), 26);
+        assert_eq!(parse_window_id("active", &backend).unwrap(), 42);
+        assert!(parse_window_id("nope", &backend).is_err());
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::hotkeys::{default_bindings, Action, Binding, KeyCombo};
+
+/// Most desktops the pager can lay out in one row.
+const MAX_DESKTOPS: u32 = 64;
+
+/// User settings from `~/.config/xdeskie/config.toml`.
+///
+/// Every field is optional in the file; missing ones take the defaults
+/// xdeskie has always used. Unknown fields are rejected so typos don't go
+/// unnoticed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Desktop count, names and navigation
+    pub desktops: DesktopsConfig,
+    /// Pager toolbar appearance and mouse buttons
+    pub pager: PagerConfig,
+    /// Desktop identification popup
+    pub popup: PopupConfig,
+    /// Daemon hotkeys
+    pub keys: KeysConfig,
+}
+
+/// `[desktops]` section.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DesktopsConfig {
+    /// Number of desktops when no state has been saved yet
+    pub count: u32,
+    /// Names of the first desktops when no state has been saved yet
+    pub names: Vec<String>,
+    /// Whether `next` and `prev` wrap around at the ends
+    pub wrap: bool,
+}
+
+/// `[pager]` section.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PagerConfig {
+    /// Initial width and height of a desktop cell in pixels
+    pub cell_size: u16,
+    /// Space around and between cells in pixels
+    pub padding: u16,
+    /// Window border width in pixels
+    pub border: u16,
+    /// Whether the mouse wheel wraps around at the ends
+    pub scroll_wraps: bool,
+    /// Mouse buttons for each action
+    pub buttons: PagerButtons,
+}
+
+/// `[pager.buttons]` section: X button numbers, 0 to disable an action.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PagerButtons {
+    /// Switch to the clicked desktop
+    pub switch: u8,
+    /// Pick a window and move it to the clicked desktop
+    #[serde(rename = "move")]
+    pub move_window: u8,
+    /// Switch to the previous desktop
+    pub previous: u8,
+    /// Switch to the next desktop
+    pub next: u8,
+}
+
+/// `[popup]` section.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PopupConfig {
+    /// Width and height in pixels
+    pub size: u16,
+    /// How long it stays up in milliseconds
+    pub duration_ms: u64,
+}
+
+/// `[keys]` section.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeysConfig {
+    /// Bind Mod4+1..9 and Mod4+Shift+1..9
+    pub defaults: bool,
+    /// Key combo -> action, e.g. "Mod4+Right" = "next"
+    pub bindings: BTreeMap<String, String>,
+}
+
+impl Default for DesktopsConfig {
+    fn default() -> Self {
+        Self {
+            count: 4,
+            names: Vec::new(),
+            wrap: true,
+        }
+    }
+}
+
+impl Default for PagerConfig {
+    fn default() -> Self {
+        Self {
+            cell_size: 32,
+            padding: 4,
+            border: 2,
+            scroll_wraps: false,
+            buttons: PagerButtons::default(),
+        }
+    }
+}
+
+impl Default for PagerButtons {
+    fn default() -> Self {
+        Self {
+            switch: 1,
+            move_window: 3,
+            previous: 4,
+            next: 5,
+        }
+    }
+}
+
+impl Default for PopupConfig {
+    fn default() -> Self {
+        Self {
+            size: 60,
+            duration_ms: 1000,
+        }
+    }
+}
+
+impl Config {
+    /// Load and validate the config file, or use defaults if there is none.
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&path)?;
+        Self::parse(&content).map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+
+    /// Load the config file, warning and using defaults if it is broken.
+    /// Only `config check` reports a bad file as an error.
+    pub fn load_or_default() -> Self {
+        Self::load().unwrap_or_else(|e| {
+            eprintln!("xdeskie: warning: {}; using defaults", e);
+            Self::default()
+        })
+    }
+
+    /// Parse and validate config file contents.
+    pub fn parse(content: &str) -> Result<Self> {
+        let config: Config = toml::from_str(content)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check values serde can't, naming the offending field.
+    pub fn validate(&self) -> Result<()> {
+        let desktops = &self.desktops;
+        if !(1..=MAX_DESKTOPS).contains(&desktops.count) {
+            return Err(anyhow!("desktops.count must be between 1 and {}", MAX_DESKTOPS));
+        }
+        if desktops.names.len() > desktops.count as usize {
+            return Err(anyhow!(
+                "desktops.names has {} names but desktops.count is {}",
+                desktops.names.len(),
+                desktops.count
+            ));
+        }
+
+        let pager = &self.pager;
+        in_range("pager.cell_size", pager.cell_size.into(), 16, 512)?;
+        in_range("pager.padding", pager.padding.into(), 0, 64)?;
+        in_range("pager.border", pager.border.into(), 0, 32)?;
+
+        let buttons = [
+            ("switch", pager.buttons.switch),
+            ("move", pager.buttons.move_window),
+            ("previous", pager.buttons.previous),
+            ("next", pager.buttons.next),
+        ];
+        for (i, &(name, button)) in buttons.iter().enumerate() {
+            in_range(&format!("pager.buttons.{}", name), button.into(), 0, 9)?;
+            if let Some((other, _)) = buttons[..i].iter().find(|&&(_, b)| b == button && b != 0) {
+                return Err(anyhow!(
+                    "pager.buttons.{} and pager.buttons.{} are both button {}",
+                    other,
+                    name,
+                    button
+                ));
+            }
+        }
+
+        in_range("popup.size", self.popup.size.into(), 20, 1000)?;
+        in_range("popup.duration_ms", self.popup.duration_ms, 1, 60_000)?;
+
+        self.keys.bindings().map(|_| ())
+    }
+
+    /// Location of the config file
+    pub fn path() -> Result<PathBuf> {
+        let config_dir = dirs::config_dir().ok_or_else(|| anyhow!("Cannot find config directory"))?;
+        Ok(config_dir.join("xdeskie").join("config.toml"))
+    }
+}
+
+impl KeysConfig {
+    /// All bindings to grab: the defaults if enabled, then the configured
+    /// ones, which replace defaults for the same keys.
+    pub fn bindings(&self) -> Result<Vec<Binding>> {
+        let mut bindings = if self.defaults { default_bindings() } else { Vec::new() };
+
+        for (combo, action) in &self.bindings {
+            let combo: KeyCombo = combo
+                .parse()
+                .map_err(|e| anyhow!("keys.bindings.\"{}\": {}", combo, e))?;
+            let action: Action = action
+                .parse()
+                .map_err(|e| anyhow!("keys.bindings.\"{}\": {}", combo, e))?;
+            bindings.push(Binding { combo, action });
+        }
+        Ok(bindings)
+    }
+}
+
+fn in_range(field: &str, value: u64, min: u64, max: u64) -> Result<()> {
+    if (min..=max).contains(&value) {
+        Ok(())
+    } else {
+        Err(anyhow!("{} must be between {} and {}, not {}", field, min, max, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_fields_take_defaults() {
+        let config = Config::parse(
+            r#"
+            [desktops]
+            count = 6
+            names = ["web", "mail"]
+
+            [pager.buttons]
+            move = 2
+
+            [keys.bindings]
+            "Mod4+Right" = "next"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.desktops.count, 6);
+        assert!(config.desktops.wrap);
+        assert_eq!(config.pager.cell_size, 32);
+        assert_eq!(config.pager.buttons.move_window, 2);
+        assert_eq!(config.pager.buttons.switch, 1);
+        assert_eq!(config.keys.bindings().unwrap()[0].action, Action::Next);
+
+        let dumped = toml::to_string(&config).unwrap();
+        assert_eq!(Config::parse(&dumped).unwrap(), config);
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn invalid_values_are_reported_by_field() {
+        let error = |content: &str| Config::parse(content).unwrap_err().to_string();
+
+        assert!(error("[desktops]\ncount = 0").contains("desktops.count"));
+        assert!(error("[desktops]\ncount = 1\nnames = [\"a\", \"b\"]").contains("desktops.names"));
+        assert!(error("[pager]\ncell_size = 8").contains("pager.cell_size must be between 16 and 512, not 8"));
+        assert!(error("[pager.buttons]\nnext = 1").contains("pager.buttons.switch and pager.buttons.next"));
+        assert!(error("[popup]\nduration_ms = 0").contains("popup.duration_ms"));
+        assert!(error("[keys.bindings]\n\"Mod4+Nope\" = \"next\"").contains("Unknown key 'Nope'"));
+        assert!(error("[pager]\ncolour = 1").contains("unknown field `colour`"));
+    }
+}
@@ -0,0 +1,350 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+use anyhow::{anyhow, Result};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    Atom, ChangeWindowAttributesAux, ClientMessageEvent, ConnectionExt, EventMask, FocusInEvent,
+    NotifyDetail, NotifyMode,
+};
+use x11rb::protocol::Event;
+
+use crate::backend::Backend;
+use crate::commands::window::apply_move_window;
+use crate::commands::{
+    move_window, record_focus, set_desktop_count, show_desktop, switch_to_desktop,
+    track_new_windows,
+};
+use crate::config::Config;
+use crate::ewmh::{
+    ALL_DESKTOPS, NET_CURRENT_DESKTOP, NET_NUMBER_OF_DESKTOPS, NET_SHOWING_DESKTOP, NET_WM_DESKTOP,
+    NET_WM_STATE, NET_WM_STATE_STICKY,
+};
+use crate::hotkeys::{Binding, Hotkeys};
+use crate::ipc;
+use crate::state::DesktopState;
+use crate::x11::X11Connection;
+
+/// A desktop change requested by another client through an EWMH ClientMessage.
+///
+/// Desktops are 0-indexed as in EWMH, not as in `move_window`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request {
+    /// _NET_CURRENT_DESKTOP: switch to a desktop
+    SwitchDesktop(u32),
+    /// _NET_WM_DESKTOP: move a window to a desktop, or `ALL_DESKTOPS`
+    MoveWindow {
+        /// Client or toplevel window
+        window: u32,
+        /// Target desktop
+        desktop: u32,
+    },
+    /// _NET_NUMBER_OF_DESKTOPS: change the number of desktops
+    SetDesktopCount(u32),
+    /// _NET_SHOWING_DESKTOP: hide or restore the current desktop's windows
+    ShowDesktop(bool),
+    /// _NET_WM_STATE with _NET_WM_STATE_STICKY: show a window on all desktops
+    SetSticky {
+        /// Client or toplevel window
+        window: u32,
+        /// Whether to make it sticky; None toggles
+        sticky: Option<bool>,
+    },
+}
+
+// _NET_WM_STATE client message actions
+const STATE_REMOVE: u32 = 0;
+const STATE_ADD: u32 = 1;
+const STATE_TOGGLE: u32 = 2;
+
+/// Atoms of the client messages the daemon understands
+struct Atoms {
+    current_desktop: Atom,
+    wm_desktop: Atom,
+    number_of_desktops: Atom,
+    wm_state: Atom,
+    wm_state_sticky: Atom,
+    showing_desktop: Atom,
+}
+
+impl Atoms {
+    fn intern(conn: &impl Connection) -> Result<Self> {
+        Ok(Self {
+            current_desktop: conn.intern_atom(false, NET_CURRENT_DESKTOP)?.reply()?.atom,
+            wm_desktop: conn.intern_atom(false, NET_WM_DESKTOP)?.reply()?.atom,
+            number_of_desktops: conn.intern_atom(false, NET_NUMBER_OF_DESKTOPS)?.reply()?.atom,
+            wm_state: conn.intern_atom(false, NET_WM_STATE)?.reply()?.atom,
+            wm_state_sticky: conn.intern_atom(false, NET_WM_STATE_STICKY)?.reply()?.atom,
+            showing_desktop: conn.intern_atom(false, NET_SHOWING_DESKTOP)?.reply()?.atom,
+        })
+    }
+
+    /// Translate a ClientMessage into a request, if it is one of ours
+    fn parse(&self, ev: &ClientMessageEvent) -> Option<Request> {
+        if ev.format != 32 {
+            return None;
+        }
+        let data = ev.data.as_data32();
+
+        if ev.type_ == self.current_desktop {
+            Some(Request::SwitchDesktop(data[0]))
+        } else if ev.type_ == self.wm_desktop {
+            Some(Request::MoveWindow {
+                window: ev.window,
+                desktop: data[0],
+            })
+        } else if ev.type_ == self.number_of_desktops {
+            Some(Request::SetDesktopCount(data[0]))
+        } else if ev.type_ == self.showing_desktop {
+            Some(Request::ShowDesktop(data[0] != 0))
+        } else if ev.type_ == self.wm_state && data[1..3].contains(&self.wm_state_sticky) {
+            let sticky = match data[0] {
+                STATE_REMOVE => Some(false),
+                STATE_ADD => Some(true),
+                STATE_TOGGLE => None,
+                _ => return None,
+            };
+            Some(Request::SetSticky {
+                window: ev.window,
+                sticky,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Run in the background, acting on EWMH requests from pagers, panels,
+/// wmctrl and xdotool, assigning windows to desktops as soon as they appear
+/// and remembering the focused window of each desktop. Commands forwarded
+/// by the CLI are answered on a Unix socket, and `bindings` are grabbed as
+/// global hotkeys that behave as `config` says. Runs until the X connection
+/// is closed.
+pub fn run_daemon(
+    x11: &X11Connection,
+    state: &mut DesktopState,
+    config: &Config,
+    bindings: &[Binding],
+) -> Result<()> {
+    let server = match ipc::socket_path(x11.display_name(), x11.screen_num()) {
+        Some(path) => Some(ipc::Server::bind(&path)?),
+        None => {
+            eprintln!("xdeskie: XDG_RUNTIME_DIR is not set; commands will not be forwarded");
+            None
+        }
+    };
+
+    // Commands forwarded here work on this state without reloading it
+    state.keep_resident(x11, &config.desktops)?;
+    let state = Mutex::new(state);
+    let stopped = AtomicBool::new(false);
+
+    thread::scope(|scope| {
+        if let Some(server) = &server {
+            scope.spawn(|| {
+                server.serve(
+                    |request| answer_request(x11, &mut lock(&state), &request),
+                    || stopped.load(Ordering::SeqCst),
+                )
+            });
+        }
+
+        let result = handle_events(x11, &state, config, bindings);
+        stopped.store(true, Ordering::SeqCst);
+        if let Some(server) = &server {
+            server.wake();
+        }
+        result
+    })
+}
+
+/// Lock the daemon's state; a panic while answering a forwarded command
+/// must not stop X events from being handled.
+fn lock<'a, 'b>(state: &'a Mutex<&'b mut DesktopState>) -> MutexGuard<'a, &'b mut DesktopState> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Run a forwarded CLI command against the daemon's state, as the CLI
+/// itself would.
+fn answer_request(x11: &X11Connection, state: &mut DesktopState, request: &ipc::Request) -> ipc::Response {
+    let result = request.execute(x11, state);
+
+    match result {
+        Ok(output) => ipc::Response::Ok { output },
+        Err(e) => ipc::Response::Error {
+            message: e.to_string(),
+        },
+    }
+}
+
+/// Handle X events until the connection is closed.
+fn handle_events(
+    x11: &X11Connection,
+    state: &Mutex<&mut DesktopState>,
+    config: &Config,
+    bindings: &[Binding],
+) -> Result<()> {
+    let conn = x11.conn();
+
+    // EWMH clients send these with SubstructureNotify|SubstructureRedirect,
+    // so selecting only SubstructureNotify receives them without competing
+    // with the window manager, which owns SubstructureRedirect. It also
+    // reports toplevel windows being created, mapped and destroyed
+    conn.change_window_attributes(
+        x11.root(),
+        &ChangeWindowAttributesAux::new().event_mask(EventMask::SUBSTRUCTURE_NOTIFY),
+    )?;
+    conn.flush()?;
+
+    let atoms = Atoms::intern(conn)?;
+    let hotkeys = Hotkeys::grab(x11, bindings)?;
+
+    // Follow focus on every toplevel to remember it per desktop
+    for window in conn.query_tree(x11.root())?.reply()?.children {
+        select_focus_events(conn, window)?;
+    }
+    conn.flush()?;
+
+    // Assign existing windows and publish the EWMH properties right away
+    // so tools can find the desktops
+    track_new_windows(x11, &mut lock(state))?;
+
+    loop {
+        // Handle everything queued before tracking windows once, so a
+        // switch unmapping many windows doesn't cost one update per window
+        let mut event = conn.wait_for_event()?;
+        let mut windows_changed = false;
+        loop {
+            match event {
+                Event::ClientMessage(ev) => {
+                    if let Some(request) = atoms.parse(&ev) {
+                        if let Err(e) = handle_request(x11, &mut lock(state), request) {
+                            eprintln!("xdeskie: {:?} failed: {}", request, e);
+                        }
+                    }
+                }
+                Event::CreateNotify(ev) if ev.parent == x11.root() => {
+                    select_focus_events(conn, ev.window)?;
+                    windows_changed = true;
+                }
+                Event::MapNotify(ev) if ev.event == x11.root() => windows_changed = true,
+                Event::DestroyNotify(ev) if ev.event == x11.root() => windows_changed = true,
+                Event::KeyPress(ev) => {
+                    if let Some(action) = hotkeys.action(&ev) {
+                        if let Err(e) = action.run(x11, &mut lock(state), config.desktops.wrap) {
+                            eprintln!("xdeskie: {} failed: {}", action, e);
+                        }
+                    }
+                }
+                Event::FocusIn(ev) if is_real_focus_change(&ev) => {
+                    // The window may already be gone
+                    let _ = record_focus(x11, &mut lock(state), ev.event);
+                }
+                _ => {}
+            }
+            match conn.poll_for_event()? {
+                Some(next) => event = next,
+                None => break,
+            }
+        }
+
+        conn.flush()?;
+        if windows_changed {
+            if let Err(e) = track_new_windows(x11, &mut lock(state)) {
+                eprintln!("xdeskie: tracking windows failed: {}", e);
+            }
+        }
+    }
+}
+
+/// Ask for FocusIn events on a toplevel window. Errors for windows that
+/// were destroyed in the meantime arrive asynchronously and are ignored.
+fn select_focus_events(conn: &impl Connection, window: u32) -> Result<()> {
+    conn.change_window_attributes(
+        window,
+        &ChangeWindowAttributesAux::new().event_mask(EventMask::FOCUS_CHANGE),
+    )?;
+    Ok(())
+}
+
+/// Whether a FocusIn event means a window really received keyboard focus,
+/// rather than a pointer-root transition or a keyboard grab.
+fn is_real_focus_change(ev: &FocusInEvent) -> bool {
+    !matches!(ev.mode, NotifyMode::GRAB | NotifyMode::UNGRAB)
+        && !matches!(
+            ev.detail,
+            NotifyDetail::POINTER | NotifyDetail::POINTER_ROOT | NotifyDetail::NONE
+        )
+}
+
+/// Carry out a request through the same operations as the CLI.
+///
+/// Requests are ignored while an EWMH window manager provides desktops:
+/// they are meant for it, and forwarding them would loop back to us.
+pub fn handle_request(backend: &impl Backend, state: &mut DesktopState, request: Request) -> Result<()> {
+    if backend.has_ewmh_desktops()? {
+        return Ok(());
+    }
+
+    match request {
+        Request::SwitchDesktop(desktop) => {
+            if desktop >= state.desktops {
+                return Err(anyhow!("No desktop {}", desktop));
+            }
+            switch_to_desktop(backend, state, desktop)
+        }
+        Request::MoveWindow { window, desktop } => {
+            let desktop = if desktop == ALL_DESKTOPS { 0 } else { desktop + 1 };
+            move_window(backend, state, window, desktop)
+        }
+        Request::SetDesktopCount(count) => set_desktop_count(backend, state, count),
+        Request::ShowDesktop(show) => show_desktop(backend, state, Some(show)).map(|_| ()),
+        Request::SetSticky { window, sticky } => state.transaction(backend, |state| {
+            let window = backend.toplevel_window(window)?;
+            let is_sticky = state.windows.get(&window.to_string()) == Some(&0);
+            if sticky.unwrap_or(!is_sticky) == is_sticky {
+                return Ok(());
+            }
+
+            // Unsticking leaves the window on the desktop being shown
+            let desktop = if is_sticky { state.current + 1 } else { 0 };
+            apply_move_window(backend, state, window, desktop)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ewmh_client_messages() {
+        let atoms = Atoms {
+            current_desktop: 1,
+            wm_desktop: 2,
+            number_of_desktops: 3,
+            wm_state: 4,
+            wm_state_sticky: 5,
+            showing_desktop: 9,
+        };
+        let message = |type_: Atom, data: u32| ClientMessageEvent::new(32, 0x400001, type_, [data, 0, 0, 0, 0]);
+
+        assert_eq!(atoms.parse(&message(1, 2)), Some(Request::SwitchDesktop(2)));
+        assert_eq!(
+            atoms.parse(&message(2, ALL_DESKTOPS)),
+            Some(Request::MoveWindow { window: 0x400001, desktop: ALL_DESKTOPS })
+        );
+        assert_eq!(atoms.parse(&message(3, 6)), Some(Request::SetDesktopCount(6)));
+        assert_eq!(atoms.parse(&message(9, 1)), Some(Request::ShowDesktop(true)));
+        assert_eq!(atoms.parse(&message(6, 0)), None);
+
+        let sticky = ClientMessageEvent::new(32, 0x400001, 4u32, [STATE_TOGGLE, 0, 5, 0, 0]);
+        assert_eq!(
+            atoms.parse(&sticky),
+            Some(Request::SetSticky { window: 0x400001, sticky: None })
+        );
+        let maximize = ClientMessageEvent::new(32, 0x400001, 4u32, [STATE_ADD, 7, 8, 0, 0]);
+        assert_eq!(atoms.parse(&maximize), None);
+    }
+}
@@ -0,0 +1,372 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+
+use anyhow::Result;
+use serde::Serialize;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt, EventMask};
+use x11rb::protocol::Event;
+
+use crate::backend::Backend;
+use crate::ewmh::{NET_CURRENT_DESKTOP, NET_NUMBER_OF_DESKTOPS, NET_WM_DESKTOP, NET_WM_STATE};
+use crate::state::{DesktopState, PROP_COUNT, PROP_CURRENT, PROP_WINDOW_DESKTOP};
+use crate::x11::X11Connection;
+
+/// Kinds of change that can be watched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    /// The current desktop changed
+    Desktop,
+    /// A window was assigned to a desktop or moved
+    Window,
+    /// The number of desktops changed
+    Count,
+    /// A window started asking for attention
+    Urgent,
+}
+
+/// A change to desktops or windows, printed by `xdeskie subscribe` as one
+/// JSON object per line.
+///
+/// Desktops are numbered as on the command line: 1-indexed, with 0
+/// meaning sticky for windows.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum DesktopEvent {
+    /// The current desktop changed
+    Desktop {
+        /// New current desktop
+        current: u32,
+        /// Its name, or its number if unnamed
+        name: String,
+    },
+    /// A window was assigned to a desktop for the first time or moved
+    Window {
+        /// Toplevel window ID
+        window: u32,
+        /// Desktop it is on now
+        desktop: u32,
+        /// Desktop it was on before, None for new windows
+        previous: Option<u32>,
+    },
+    /// The number of desktops changed
+    Count {
+        /// New number of desktops
+        desktops: u32,
+    },
+    /// A window set the WM_HINTS urgency flag or _NET_WM_STATE_DEMANDS_ATTENTION
+    Urgent {
+        /// Toplevel window ID
+        window: u32,
+        /// Desktop the window is on
+        desktop: u32,
+    },
+}
+
+impl DesktopEvent {
+    /// Which kind of change this is
+    pub fn kind(&self) -> EventKind {
+        match self {
+            DesktopEvent::Desktop { .. } => EventKind::Desktop,
+            DesktopEvent::Window { .. } => EventKind::Window,
+            DesktopEvent::Count { .. } => EventKind::Count,
+            DesktopEvent::Urgent { .. } => EventKind::Urgent,
+        }
+    }
+}
+
+/// The parts of desktop state that events report on.
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    current: u32,
+    label: String,
+    desktops: u32,
+    windows: HashMap<String, u32>,
+    urgent: HashSet<u32>,
+}
+
+impl Snapshot {
+    /// Capture state along with the windows currently asking for attention.
+    pub fn take(state: &DesktopState, urgent: HashSet<u32>) -> Self {
+        Self {
+            current: state.current,
+            label: state.desktop_label(state.current),
+            desktops: state.desktops,
+            windows: state.windows.clone(),
+            urgent,
+        }
+    }
+
+    /// Events describing the state as it is, for new subscribers.
+    pub fn initial_events(&self) -> Vec<DesktopEvent> {
+        vec![
+            DesktopEvent::Desktop {
+                current: self.current + 1,
+                name: self.label.clone(),
+            },
+            DesktopEvent::Count {
+                desktops: self.desktops,
+            },
+        ]
+    }
+
+    /// Events describing what changed between an older snapshot and this one.
+    pub fn changes_since(&self, old: &Snapshot) -> Vec<DesktopEvent> {
+        let mut events = Vec::new();
+
+        if self.desktops != old.desktops {
+            events.push(DesktopEvent::Count {
+                desktops: self.desktops,
+            });
+        }
+        if self.current != old.current {
+            events.push(DesktopEvent::Desktop {
+                current: self.current + 1,
+                name: self.label.clone(),
+            });
+        }
+
+        let mut windows: Vec<(u32, u32)> = self
+            .windows
+            .iter()
+            .filter_map(|(key, &desktop)| Some((key.parse().ok()?, desktop)))
+            .collect();
+        windows.sort_unstable();
+
+        for &(window, desktop) in &windows {
+            let previous = old.windows.get(&window.to_string()).copied();
+            if previous != Some(desktop) {
+                events.push(DesktopEvent::Window {
+                    window,
+                    desktop,
+                    previous,
+                });
+            }
+        }
+        for &(window, desktop) in &windows {
+            if self.urgent.contains(&window) && !old.urgent.contains(&window) {
+                events.push(DesktopEvent::Urgent { window, desktop });
+            }
+        }
+
+        events
+    }
+}
+
+/// Turns X events into desktop events.
+///
+/// Watches the root window properties xdeskie and EWMH window managers
+/// publish, and, when window or urgency events are wanted, the desktop and
+/// hint properties of every toplevel window and its client.
+pub struct Watcher {
+    kinds: HashSet<EventKind>,
+    root_atoms: Vec<Atom>,
+    window_atoms: Vec<Atom>,
+    snapshot: Snapshot,
+    /// Whether events were noticed since the last resync
+    stale: bool,
+}
+
+impl Watcher {
+    /// Start watching for the given kinds of change.
+    ///
+    /// Selects PropertyChange on the root window, plus SubstructureNotify
+    /// when watching windows, replacing any event mask this connection
+    /// already selected there.
+    pub fn new(x11: &X11Connection, state: &mut DesktopState, kinds: &[EventKind]) -> Result<Self> {
+        let conn = x11.conn();
+        let intern = |name: &[u8]| -> Result<Atom> { Ok(conn.intern_atom(false, name)?.reply()?.atom) };
+
+        let mut watcher = Self {
+            kinds: kinds.iter().copied().collect(),
+            root_atoms: vec![
+                intern(PROP_CURRENT)?,
+                intern(PROP_COUNT)?,
+                intern(NET_CURRENT_DESKTOP)?,
+                intern(NET_NUMBER_OF_DESKTOPS)?,
+            ],
+            window_atoms: vec![
+                intern(PROP_WINDOW_DESKTOP)?,
+                intern(NET_WM_DESKTOP)?,
+                intern(NET_WM_STATE)?,
+                AtomEnum::WM_HINTS.into(),
+            ],
+            snapshot: Snapshot::default(),
+            stale: false,
+        };
+
+        let mut root_mask = EventMask::PROPERTY_CHANGE;
+        if watcher.watches_windows() {
+            root_mask |= EventMask::SUBSTRUCTURE_NOTIFY;
+        }
+        conn.change_window_attributes(x11.root(), &ChangeWindowAttributesAux::new().event_mask(root_mask))?;
+        if watcher.watches_windows() {
+            for window in x11.get_toplevel_windows()? {
+                watcher.watch_window(x11, window)?;
+            }
+        }
+        conn.flush()?;
+
+        state.sync_from_x(x11)?;
+        watcher.snapshot = watcher.take_snapshot(x11, state);
+        Ok(watcher)
+    }
+
+    /// Events describing the state as it is, for new subscribers.
+    pub fn initial_events(&self) -> Vec<DesktopEvent> {
+        self.filter(self.snapshot.initial_events())
+    }
+
+    /// Note an X event that may have changed what is watched.
+    ///
+    /// Nothing is reread yet: call `changes` once the queued events are
+    /// handled, so a switch that rewrites many properties costs one resync.
+    pub fn notice(&mut self, x11: &X11Connection, event: &Event) -> Result<()> {
+        match event {
+            // Clients are reparented into frames by the time they map
+            Event::MapNotify(ev) if ev.event == x11.root() && self.watches_windows() => {
+                self.watch_window(x11, ev.window)?;
+                x11.conn().flush()?;
+            }
+            Event::PropertyNotify(ev) if ev.window == x11.root() && self.root_atoms.contains(&ev.atom) => {}
+            Event::PropertyNotify(ev) if ev.window != x11.root() && self.window_atoms.contains(&ev.atom) => {}
+            _ => return Ok(()),
+        }
+        self.stale = true;
+        Ok(())
+    }
+
+    /// Update state if noticed events call for it and return the changes
+    /// they revealed.
+    pub fn changes(&mut self, x11: &X11Connection, state: &mut DesktopState) -> Result<Vec<DesktopEvent>> {
+        if !std::mem::take(&mut self.stale) {
+            return Ok(Vec::new());
+        }
+
+        state.sync_from_x(x11)?;
+        let snapshot = self.take_snapshot(x11, state);
+        let events = snapshot.changes_since(&self.snapshot);
+        self.snapshot = snapshot;
+        Ok(self.filter(events))
+    }
+
+    fn watches_windows(&self) -> bool {
+        self.kinds.contains(&EventKind::Window) || self.kinds.contains(&EventKind::Urgent)
+    }
+
+    /// Select property changes on a toplevel window and its client.
+    /// The window may already be gone, which is not an error.
+    fn watch_window(&self, x11: &X11Connection, window: u32) -> Result<()> {
+        let client = x11.client_window(window).unwrap_or(window);
+        let aux = ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE);
+        for window in [window, client] {
+            x11.conn().change_window_attributes(window, &aux)?;
+        }
+        Ok(())
+    }
+
+    fn take_snapshot(&self, x11: &X11Connection, state: &DesktopState) -> Snapshot {
+        let urgent = if self.kinds.contains(&EventKind::Urgent) {
+            x11.get_toplevel_windows()
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|&window| x11.is_urgent(window).unwrap_or(false))
+                .collect()
+        } else {
+            HashSet::new()
+        };
+        Snapshot::take(state, urgent)
+    }
+
+    fn filter(&self, events: Vec<DesktopEvent>) -> Vec<DesktopEvent> {
+        events.into_iter().filter(|e| self.kinds.contains(&e.kind())).collect()
+    }
+}
+
+/// Print events as JSON lines on stdout until the X connection closes or
+/// stdout does, starting with the current desktop and count.
+pub fn subscribe(x11: &X11Connection, state: &mut DesktopState, kinds: &[EventKind]) -> Result<()> {
+    let mut watcher = Watcher::new(x11, state, kinds)?;
+    let mut out = io::stdout().lock();
+
+    let mut events = watcher.initial_events();
+    loop {
+        for event in &events {
+            let written = writeln!(out, "{}", serde_json::to_string(event)?).and_then(|_| out.flush());
+            match written {
+                // The bar or script reading us went away
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+                result => result?,
+            }
+        }
+
+        let mut event = x11.conn().wait_for_event()?;
+        loop {
+            watcher.notice(x11, &event)?;
+            match x11.conn().poll_for_event()? {
+                Some(next) => event = next,
+                None => break,
+            }
+        }
+        events = watcher.changes(x11, state)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_what_changed_between_snapshots() {
+        let mut state = DesktopState {
+            desktops: 4,
+            ..Default::default()
+        };
+        state.set_window_desktop(10, 1);
+        state.set_window_desktop(20, 1);
+        let old = Snapshot::take(&state, HashSet::new());
+
+        state.current = 2;
+        state.desktops = 5;
+        state.set_desktop_name(2, "mail");
+        state.set_window_desktop(20, 0);
+        state.set_window_desktop(30, 3);
+        let new = Snapshot::take(&state, HashSet::from([10]));
+
+        assert_eq!(
+            new.changes_since(&old),
+            vec![
+                DesktopEvent::Count { desktops: 5 },
+                DesktopEvent::Desktop {
+                    current: 3,
+                    name: "mail".to_string()
+                },
+                DesktopEvent::Window {
+                    window: 20,
+                    desktop: 0,
+                    previous: Some(1)
+                },
+                DesktopEvent::Window {
+                    window: 30,
+                    desktop: 3,
+                    previous: None
+                },
+                DesktopEvent::Urgent { window: 10, desktop: 1 },
+            ]
+        );
+        assert!(new.changes_since(&new).is_empty());
+    }
+
+    #[test]
+    fn events_are_json_lines() {
+        let event = DesktopEvent::Window {
+            window: 0x400001,
+            desktop: 2,
+            previous: None,
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"window","window":4194305,"desktop":2,"previous":null}"#
+        );
+    }
+}
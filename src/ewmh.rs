@@ -0,0 +1,311 @@
+use anyhow::Result;
+
+use crate::backend::Backend;
+use crate::state::DesktopState;
+
+/// Hints this shim implements
+pub const NET_SUPPORTED: &[u8] = b"_NET_SUPPORTED";
+/// Check window proving an EWMH manager is running
+pub const NET_SUPPORTING_WM_CHECK: &[u8] = b"_NET_SUPPORTING_WM_CHECK";
+/// Number of desktops
+pub const NET_NUMBER_OF_DESKTOPS: &[u8] = b"_NET_NUMBER_OF_DESKTOPS";
+/// Current desktop (0-indexed)
+pub const NET_CURRENT_DESKTOP: &[u8] = b"_NET_CURRENT_DESKTOP";
+/// Desktop names
+pub const NET_DESKTOP_NAMES: &[u8] = b"_NET_DESKTOP_NAMES";
+/// Managed client windows in creation order
+pub const NET_CLIENT_LIST: &[u8] = b"_NET_CLIENT_LIST";
+/// Managed client windows in stacking order (bottom to top)
+pub const NET_CLIENT_LIST_STACKING: &[u8] = b"_NET_CLIENT_LIST_STACKING";
+/// Desktop of a client window
+pub const NET_WM_DESKTOP: &[u8] = b"_NET_WM_DESKTOP";
+/// Focused client window
+pub const NET_ACTIVE_WINDOW: &[u8] = b"_NET_ACTIVE_WINDOW";
+/// Whether windows are hidden to show the desktop
+pub const NET_SHOWING_DESKTOP: &[u8] = b"_NET_SHOWING_DESKTOP";
+/// States of a client window
+pub const NET_WM_STATE: &[u8] = b"_NET_WM_STATE";
+/// State of windows shown on all desktops
+pub const NET_WM_STATE_STICKY: &[u8] = b"_NET_WM_STATE_STICKY";
+/// State of windows asking for the user's attention
+pub const NET_WM_STATE_DEMANDS_ATTENTION: &[u8] = b"_NET_WM_STATE_DEMANDS_ATTENTION";
+/// Functional types of a client window
+pub const NET_WM_WINDOW_TYPE: &[u8] = b"_NET_WM_WINDOW_TYPE";
+/// Window type of docks and panels
+pub const NET_WM_WINDOW_TYPE_DOCK: &[u8] = b"_NET_WM_WINDOW_TYPE_DOCK";
+
+/// _NET_WM_DESKTOP value for windows shown on all desktops
+pub const ALL_DESKTOPS: u32 = 0xFFFFFFFF;
+
+/// Timestamp meaning "now" in client messages
+const CURRENT_TIME: u32 = 0;
+
+/// Source indication for requests from pagers and other direct user actions
+const SOURCE_PAGER: u32 = 2;
+
+/// Everything advertised in _NET_SUPPORTED
+const SUPPORTED: &[&[u8]] = &[
+    NET_SUPPORTED,
+    NET_SUPPORTING_WM_CHECK,
+    NET_NUMBER_OF_DESKTOPS,
+    NET_CURRENT_DESKTOP,
+    NET_DESKTOP_NAMES,
+    NET_CLIENT_LIST,
+    NET_CLIENT_LIST_STACKING,
+    NET_WM_DESKTOP,
+    NET_WM_STATE,
+    NET_WM_STATE_STICKY,
+    NET_SHOWING_DESKTOP,
+    NET_ACTIVE_WINDOW,
+];
+
+/// Publish desktop state as EWMH root window properties.
+///
+/// TWM and similar window managers don't set these, so without them
+/// panels, pagers, wmctrl and xdotool can't see xdeskie's desktops.
+pub fn publish(backend: &impl Backend, state: &DesktopState) -> Result<()> {
+    backend.ensure_wm_check_window()?;
+    backend.set_root_atoms(NET_SUPPORTED, SUPPORTED)?;
+
+    backend.update_root_property(NET_NUMBER_OF_DESKTOPS, state.desktops)?;
+    backend.update_root_property(NET_CURRENT_DESKTOP, state.current)?;
+    backend.update_root_property(NET_SHOWING_DESKTOP, state.showing_desktop as u32)?;
+
+    let names: Vec<String> = (0..state.desktops).map(|d| state.desktop_label(d)).collect();
+    backend.set_root_strings(NET_DESKTOP_NAMES, &names)?;
+
+    // Clients are listed even while hidden on another desktop
+    let sticky_atom = backend.intern_atom(NET_WM_STATE_STICKY)?;
+    let mut stacking = Vec::new();
+    for window in backend.get_stacking_order()? {
+        let Some(&desktop) = state.windows.get(&window.to_string()) else {
+            continue;
+        };
+        let client = backend.client_window(window).unwrap_or(window);
+        // A window destroyed meanwhile must not fail the transaction after
+        // windows were already mapped and unmapped
+        let _ = backend.update_window_property(client, NET_WM_DESKTOP, to_ewmh_desktop(desktop));
+        let _ = publish_sticky(backend, client, sticky_atom, desktop == 0);
+        stacking.push(client);
+    }
+
+    // X hands out IDs in increasing order, which approximates creation order
+    let mut clients = stacking.clone();
+    clients.sort_unstable();
+
+    backend.set_root_windows(NET_CLIENT_LIST, &clients)?;
+    backend.set_root_windows(NET_CLIENT_LIST_STACKING, &stacking)?;
+    Ok(())
+}
+
+/// Publish the client of a focused toplevel window as _NET_ACTIVE_WINDOW.
+pub fn publish_active_window(backend: &impl Backend, window: u32) -> Result<()> {
+    let client = backend.client_window(window).unwrap_or(window);
+    backend.set_root_windows(NET_ACTIVE_WINDOW, &[client])
+}
+
+/// Add or remove _NET_WM_STATE_STICKY so a client's state matches its assignment.
+fn publish_sticky(backend: &impl Backend, client: u32, sticky_atom: u32, sticky: bool) -> Result<()> {
+    let mut states = backend.get_window_atoms(client, NET_WM_STATE)?;
+    if states.contains(&sticky_atom) == sticky {
+        return Ok(());
+    }
+
+    if sticky {
+        states.push(sticky_atom);
+    } else {
+        states.retain(|&atom| atom != sticky_atom);
+    }
+    backend.set_window_atoms(client, NET_WM_STATE, &states)
+}
+
+/// Whether a toplevel window's client asks to be on every desktop, either
+/// with _NET_WM_STATE_STICKY or by being a dock or panel.
+pub fn wants_sticky(backend: &impl Backend, window: u32) -> bool {
+    let client = backend.client_window(window).unwrap_or(window);
+    let has_atom = |property: &[u8], name: &[u8]| -> Result<bool> {
+        let atom = backend.intern_atom(name)?;
+        Ok(backend.get_window_atoms(client, property)?.contains(&atom))
+    };
+
+    has_atom(NET_WM_STATE, NET_WM_STATE_STICKY).unwrap_or(false)
+        || has_atom(NET_WM_WINDOW_TYPE, NET_WM_WINDOW_TYPE_DOCK).unwrap_or(false)
+}
+
+/// Read desktops from the window manager instead of our own properties.
+pub fn sync_from_window_manager(backend: &impl Backend, state: &mut DesktopState) -> Result<()> {
+    if let Some(count) = backend.get_root_property(NET_NUMBER_OF_DESKTOPS)?.filter(|&n| n > 0) {
+        state.desktops = count;
+    }
+    if let Some(current) = backend.get_root_property(NET_CURRENT_DESKTOP)? {
+        state.current = current.min(state.desktops - 1);
+    }
+    if let Some(showing) = backend.get_root_property(NET_SHOWING_DESKTOP)? {
+        state.showing_desktop = showing != 0;
+    }
+
+    for window in backend.get_toplevel_windows()? {
+        if let Some(desktop) = window_desktop_hint(backend, window, state.desktops) {
+            state.set_window_desktop(window, desktop);
+        }
+    }
+    Ok(())
+}
+
+/// Ask the window manager to switch to a desktop (0-indexed).
+pub fn request_switch(backend: &impl Backend, desktop: u32) -> Result<()> {
+    backend.send_client_message(None, NET_CURRENT_DESKTOP, [desktop, CURRENT_TIME, 0, 0, 0])
+}
+
+/// Ask the window manager to move a toplevel window to a desktop
+/// (0=sticky, 1+=specific).
+pub fn request_move(backend: &impl Backend, window: u32, desktop: u32) -> Result<()> {
+    let client = backend.client_window(window).unwrap_or(window);
+    let data = [to_ewmh_desktop(desktop), SOURCE_PAGER, 0, 0, 0];
+    backend.send_client_message(Some(client), NET_WM_DESKTOP, data)
+}
+
+/// Ask the window manager to enter or leave show-desktop mode.
+pub fn request_showing_desktop(backend: &impl Backend, showing: bool) -> Result<()> {
+    backend.send_client_message(None, NET_SHOWING_DESKTOP, [showing as u32, 0, 0, 0, 0])
+}
+
+/// Ask the window manager to change the number of desktops.
+pub fn request_desktop_count(backend: &impl Backend, count: u32) -> Result<()> {
+    backend.send_client_message(None, NET_NUMBER_OF_DESKTOPS, [count, 0, 0, 0, 0])
+}
+
+/// Read the _NET_WM_DESKTOP a toplevel window's client arrived with.
+///
+/// Returns an xdeskie desktop number (0=sticky, 1+=specific), or None
+/// if the hint is missing or names a desktop that doesn't exist.
+pub fn window_desktop_hint(backend: &impl Backend, window: u32, desktops: u32) -> Option<u32> {
+    let client = backend.client_window(window).unwrap_or(window);
+    match backend.get_window_property(client, NET_WM_DESKTOP).ok()?? {
+        ALL_DESKTOPS => Some(0),
+        desktop if desktop < desktops => Some(desktop + 1),
+        _ => None,
+    }
+}
+
+/// Convert an xdeskie desktop number (0=sticky, 1+=specific) to EWMH.
+fn to_ewmh_desktop(desktop: u32) -> u32 {
+    if desktop == 0 {
+        ALL_DESKTOPS
+    } else {
+        desktop - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::fake::{Call, FakeBackend, FakeWindow};
+
+    #[test]
+    fn publishes_desktops_and_client_lists() {
+        let fake = FakeBackend::new();
+        fake.add_fake_window(FakeWindow {
+            id: 30,
+            client: Some(31),
+            mapped: true,
+            ..Default::default()
+        });
+        fake.add_window(31, "client");
+        fake.add_window(10, "b");
+        fake.add_window(20, "untracked");
+
+        let mut state = DesktopState {
+            desktops: 3,
+            current: 1,
+            ..Default::default()
+        };
+        state.set_desktop_name(0, "web");
+        state.set_window_desktop(10, 1);
+        state.set_window_desktop(30, 0);
+
+        publish(&fake, &state).unwrap();
+        publish(&fake, &state).unwrap();
+
+        assert_eq!(fake.get_root_property(NET_NUMBER_OF_DESKTOPS).unwrap(), Some(3));
+        assert_eq!(fake.get_root_property(NET_CURRENT_DESKTOP).unwrap(), Some(1));
+        assert_eq!(
+            fake.root_strings("_NET_DESKTOP_NAMES").unwrap(),
+            vec!["web", "2", "3"]
+        );
+        assert_eq!(fake.root_windows("_NET_CLIENT_LIST").unwrap(), vec![10, 31]);
+        assert_eq!(fake.root_windows("_NET_CLIENT_LIST_STACKING").unwrap(), vec![31, 10]);
+        assert_eq!(fake.get_window_property(31, NET_WM_DESKTOP).unwrap(), Some(ALL_DESKTOPS));
+        assert_eq!(fake.get_window_property(10, NET_WM_DESKTOP).unwrap(), Some(0));
+        assert!(wants_sticky(&fake, 30));
+        assert!(!wants_sticky(&fake, 10));
+        assert!(fake
+            .root_atoms("_NET_SUPPORTED")
+            .unwrap()
+            .contains(&"_NET_CURRENT_DESKTOP".to_string()));
+
+        let created = fake
+            .calls()
+            .iter()
+            .filter(|&c| *c == Call::CreateWmCheckWindow)
+            .count();
+        assert_eq!(created, 1);
+    }
+
+    #[test]
+    fn publishing_skips_windows_that_are_gone() {
+        let fake = FakeBackend::new();
+        // Frame whose client was destroyed before we got to it
+        fake.add_fake_window(FakeWindow {
+            id: 30,
+            client: Some(31),
+            mapped: true,
+            ..Default::default()
+        });
+        fake.add_window(10, "b");
+
+        let mut state = DesktopState {
+            desktops: 2,
+            ..Default::default()
+        };
+        state.set_window_desktop(30, 0);
+        state.set_window_desktop(10, 1);
+
+        publish(&fake, &state).unwrap();
+
+        assert_eq!(fake.get_window_property(10, NET_WM_DESKTOP).unwrap(), Some(0));
+    }
+
+    #[test]
+    fn reads_desktops_from_window_manager() {
+        let fake = FakeBackend::new();
+        fake.add_fake_window(FakeWindow {
+            id: 30,
+            client: Some(31),
+            ..Default::default()
+        });
+        fake.add_window(31, "client");
+        fake.set_root_property(NET_NUMBER_OF_DESKTOPS, 6).unwrap();
+        fake.set_root_property(NET_CURRENT_DESKTOP, 5).unwrap();
+        fake.set_window_property(31, NET_WM_DESKTOP, 4).unwrap();
+
+        let mut state = DesktopState {
+            desktops: 4,
+            ..Default::default()
+        };
+        sync_from_window_manager(&fake, &mut state).unwrap();
+
+        assert_eq!((state.desktops, state.current), (6, 5));
+        assert_eq!(state.windows.get("30"), Some(&5));
+
+        request_move(&fake, 30, 0).unwrap();
+        assert_eq!(
+            fake.calls().last(),
+            Some(&Call::ClientMessage(
+                Some(31),
+                "_NET_WM_DESKTOP".to_string(),
+                [ALL_DESKTOPS, SOURCE_PAGER, 0, 0, 0]
+            ))
+        );
+    }
+}
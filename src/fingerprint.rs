@@ -0,0 +1,156 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::Backend;
+use crate::x11::X11Connection;
+
+/// Identifying properties of an application window.
+///
+/// Window IDs are only meaningful for the lifetime of an X session, so
+/// these are used to recognize the same application when it is launched
+/// again later.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowFingerprint {
+    /// WM_CLASS as [instance, class]
+    #[serde(default)]
+    pub class: Vec<String>,
+    /// WM_WINDOW_ROLE, if set
+    #[serde(default)]
+    pub role: Option<String>,
+    /// WM_COMMAND argument vector, if set
+    #[serde(default)]
+    pub command: Vec<String>,
+    /// Title pattern; `*` matches any run of characters
+    #[serde(default)]
+    pub title: String,
+}
+
+/// Desktop assignment of a window that no longer exists.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RememberedWindow {
+    /// Properties used to recognize the application again
+    pub fingerprint: WindowFingerprint,
+    /// Desktop number (0=sticky, 1+=specific)
+    pub desktop: u32,
+}
+
+impl WindowFingerprint {
+    /// Read the fingerprint of a toplevel window (or the client inside its frame).
+    pub fn from_window(x11: &X11Connection, window: u32) -> Result<Self> {
+        let client = x11.client_window(window)?;
+
+        Ok(Self {
+            class: x11.get_window_class(client)?,
+            role: x11.get_window_role(client)?,
+            command: x11.get_window_command(client)?,
+            title: x11.get_window_name(window)?,
+        })
+    }
+
+    /// Whether there is enough information to recognize this window later.
+    pub fn is_identifiable(&self) -> bool {
+        !self.class.is_empty()
+    }
+
+    /// Score how well a new window matches this remembered fingerprint.
+    ///
+    /// Returns None when the windows cannot be the same application:
+    /// WM_CLASS must be equal, and role and command must agree when both
+    /// windows set them. Higher scores mean more specific matches.
+    pub fn match_score(&self, other: &WindowFingerprint) -> Option<u32> {
+        if !self.is_identifiable() || self.class != other.class {
+            return None;
+        }
+
+        let mut score = 1;
+
+        match (&self.role, &other.role) {
+            (Some(a), Some(b)) if a != b => return None,
+            (Some(_), Some(_)) => score += 4,
+            _ => {}
+        }
+
+        if !self.command.is_empty() && !other.command.is_empty() {
+            if self.command != other.command {
+                return None;
+            }
+            score += 2;
+        }
+
+        if glob_match(&self.title, &other.title) {
+            score += 1;
+        }
+
+        Some(score)
+    }
+}
+
+/// Match text against a pattern where `*` matches any run of characters.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+
+    if parts.len() == 1 {
+        return pattern == text;
+    }
+
+    let first = parts[0];
+    let last = parts[parts.len() - 1];
+    if !text.starts_with(first) || !text[first.len()..].ends_with(last) {
+        return false;
+    }
+
+    let mut rest = &text[first.len()..text.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xterm() -> WindowFingerprint {
+        WindowFingerprint {
+            class: vec!["xterm".to_string(), "XTerm".to_string()],
+            title: "user@host: ~".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn glob_matches_wildcards() {
+        assert!(glob_match("user@*: ~", "user@host: ~"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "aXXbYYc"));
+        assert!(!glob_match("a*b", "ab b c"));
+        assert!(!glob_match("ab*b", "ab"));
+        assert!(glob_match("exact", "exact"));
+    }
+
+    #[test]
+    fn role_and_command_must_agree_when_set() {
+        let saved = WindowFingerprint {
+            role: Some("browser".to_string()),
+            ..xterm()
+        };
+
+        let same_role = saved.clone();
+        let other_role = WindowFingerprint {
+            role: Some("dialog".to_string()),
+            ..xterm()
+        };
+        let other_command = WindowFingerprint {
+            command: vec!["xterm".to_string()],
+            ..xterm()
+        };
+
+        assert!(saved.match_score(&same_role) > saved.match_score(&xterm()));
+        assert_eq!(saved.match_score(&other_role), None);
+        assert!(saved.match_score(&other_command).is_some());
+    }
+}
@@ -0,0 +1,18 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+/// Assignments captured before an undoable operation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Human-readable description of the operation, e.g. "switch to desktop 2"
+    pub operation: String,
+    /// Current desktop (0-indexed)
+    pub current: u32,
+    /// Total number of desktops
+    pub desktops: u32,
+    /// Window ID (as string) -> desktop number (0=sticky, 1+=specific)
+    pub windows: HashMap<String, u32>,
+    /// Windows hidden by the application itself
+    pub app_hidden: HashSet<String>,
+}
@@ -0,0 +1,415 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyError;
+use x11rb::protocol::xproto::{ConnectionExt, GrabMode, KeyPressEvent, ModMask};
+
+use crate::backend::Backend;
+use crate::commands::desktop::{switch_next, switch_prev};
+use crate::commands::show_desktop;
+use crate::ipc::Request;
+use crate::state::DesktopState;
+use crate::x11::X11Connection;
+
+/// Keysym of the Num_Lock key
+const XK_NUM_LOCK: u32 = 0xff7f;
+
+/// Modifier names accepted in key combos, with their masks
+const MODIFIERS: &[(&str, u16)] = &[
+    ("Shift", 1 << 0),
+    ("Control", 1 << 2),
+    ("Ctrl", 1 << 2),
+    ("Mod1", 1 << 3),
+    ("Alt", 1 << 3),
+    ("Mod2", 1 << 4),
+    ("Mod3", 1 << 5),
+    ("Mod4", 1 << 6),
+    ("Super", 1 << 6),
+    ("Mod5", 1 << 7),
+];
+
+/// Named keysyms accepted besides letters, digits and F1-F12
+const KEYSYMS: &[(&str, u32)] = &[
+    ("space", 0x0020),
+    ("minus", 0x002d),
+    ("equal", 0x003d),
+    ("comma", 0x002c),
+    ("period", 0x002e),
+    ("BackSpace", 0xff08),
+    ("Tab", 0xff09),
+    ("Return", 0xff0d),
+    ("Escape", 0xff1b),
+    ("Home", 0xff50),
+    ("Left", 0xff51),
+    ("Up", 0xff52),
+    ("Right", 0xff53),
+    ("Down", 0xff54),
+    ("Page_Up", 0xff55),
+    ("Page_Down", 0xff56),
+    ("End", 0xff57),
+    ("Insert", 0xff63),
+    ("Delete", 0xffff),
+];
+
+/// Keysym of F1; F2-F12 follow
+const XK_F1: u32 = 0xffbe;
+
+/// A key with modifiers, written like `Mod4+Shift+1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyCombo {
+    /// Modifier mask (Shift, Control, Mod1-Mod5)
+    pub modifiers: u16,
+    /// Keysym of the key
+    pub keysym: u32,
+}
+
+/// What a hotkey does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Switch to a desktop (number or name)
+    Switch(String),
+    /// Move the active window to a desktop (number, name or sticky)
+    Move(String),
+    /// Switch to the next desktop
+    Next,
+    /// Switch to the previous desktop
+    Prev,
+    /// Toggle show-desktop mode
+    ShowDesktop,
+}
+
+/// A key combo and the action it triggers, written like
+/// `Mod4+Shift+1=move 1`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    /// Keys to press
+    pub combo: KeyCombo,
+    /// What to do
+    pub action: Action,
+}
+
+impl FromStr for KeyCombo {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let key = parts.pop().filter(|key| !key.is_empty()).ok_or_else(|| anyhow!("No key in '{}'", s))?;
+
+        let mut modifiers = 0;
+        for part in parts {
+            let (_, mask) = MODIFIERS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(part))
+                .ok_or_else(|| anyhow!("Unknown modifier '{}'", part))?;
+            modifiers |= mask;
+        }
+
+        Ok(Self {
+            modifiers,
+            keysym: parse_keysym(key).ok_or_else(|| anyhow!("Unknown key '{}'", key))?,
+        })
+    }
+}
+
+impl fmt::Display for KeyCombo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut seen = 0;
+        for &(name, mask) in MODIFIERS {
+            if self.modifiers & mask != 0 && seen & mask == 0 {
+                write!(f, "{}+", name)?;
+                seen |= mask;
+            }
+        }
+        match keysym_name(self.keysym) {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "0x{:x}", self.keysym),
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut words = s.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let argument = words.next().map(str::to_string);
+        if words.next().is_some() {
+            return Err(anyhow!("Too many arguments in '{}'", s));
+        }
+
+        match (command, argument) {
+            ("switch", Some(desktop)) => Ok(Action::Switch(desktop)),
+            ("move", Some(desktop)) => Ok(Action::Move(desktop)),
+            ("next", None) => Ok(Action::Next),
+            ("prev", None) => Ok(Action::Prev),
+            ("show-desktop", None) => Ok(Action::ShowDesktop),
+            _ => Err(anyhow!(
+                "Unknown action '{}' (expected switch N, move N, next, prev or show-desktop)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::Switch(desktop) => write!(f, "switch {}", desktop),
+            Action::Move(desktop) => write!(f, "move {}", desktop),
+            Action::Next => write!(f, "next"),
+            Action::Prev => write!(f, "prev"),
+            Action::ShowDesktop => write!(f, "show-desktop"),
+        }
+    }
+}
+
+impl FromStr for Binding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (combo, action) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected KEYS=ACTION, like Mod4+1=switch 1"))?;
+        Ok(Self {
+            combo: combo.parse()?,
+            action: action.parse()?,
+        })
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.combo, self.action)
+    }
+}
+
+impl Action {
+    /// Carry out the action in this process; `wrap` makes next and prev
+    /// wrap around at the ends.
+    pub fn run(&self, backend: &impl Backend, state: &mut DesktopState, wrap: bool) -> Result<()> {
+        match self {
+            Action::Switch(desktop) => {
+                let request = Request::Switch {
+                    desktop: desktop.clone(),
+                };
+                request.execute(backend, state).map(|_| ())
+            }
+            Action::Move(desktop) => {
+                let request = Request::Move {
+                    window: "active".to_string(),
+                    desktop: desktop.clone(),
+                };
+                request.execute(backend, state).map(|_| ())
+            }
+            Action::Next => switch_next(backend, state, wrap).map(|_| ()),
+            Action::Prev => switch_prev(backend, state, wrap).map(|_| ()),
+            Action::ShowDesktop => show_desktop(backend, state, None).map(|_| ()),
+        }
+    }
+}
+
+/// Mod4+1..9 switch desktops and Mod4+Shift+1..9 move the active window.
+pub fn default_bindings() -> Vec<Binding> {
+    let mod4 = 1 << 6;
+    let shift = 1 << 0;
+
+    let mut bindings = Vec::new();
+    for n in 1..=9u32 {
+        let keysym = '0' as u32 + n;
+        bindings.push(Binding {
+            combo: KeyCombo { modifiers: mod4, keysym },
+            action: Action::Switch(n.to_string()),
+        });
+        bindings.push(Binding {
+            combo: KeyCombo {
+                modifiers: mod4 | shift,
+                keysym,
+            },
+            action: Action::Move(n.to_string()),
+        });
+    }
+    bindings
+}
+
+/// Look up a keysym by name: a letter, digit, F1-F12 or one of `KEYSYMS`.
+fn parse_keysym(name: &str) -> Option<u32> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        // Letters are bound by their lowercase keysym, as X reports them
+        return c.is_ascii_alphanumeric().then(|| c.to_ascii_lowercase() as u32);
+    }
+
+    if let Some(n) = name.strip_prefix('F').and_then(|n| n.parse::<u32>().ok()) {
+        return (1..=12).contains(&n).then(|| XK_F1 + n - 1);
+    }
+
+    KEYSYMS
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(name))
+        .map(|&(_, keysym)| keysym)
+}
+
+/// Name of a keysym accepted by `parse_keysym`.
+fn keysym_name(keysym: u32) -> Option<String> {
+    match keysym {
+        0x30..=0x39 | 0x61..=0x7a => Some(char::from_u32(keysym)?.to_string()),
+        k if (XK_F1..XK_F1 + 12).contains(&k) => Some(format!("F{}", k - XK_F1 + 1)),
+        k => KEYSYMS.iter().find(|&&(_, known)| known == k).map(|(name, _)| name.to_string()),
+    }
+}
+
+/// Key combos grabbed on the root window and what they do.
+pub struct Hotkeys {
+    actions: HashMap<(u8, u16), Action>,
+    /// Lock modifiers (CapsLock, NumLock) ignored when matching
+    locks: u16,
+}
+
+impl Hotkeys {
+    /// Grab every binding's combo on the root window.
+    ///
+    /// Each combo is also grabbed with CapsLock and NumLock on, since X
+    /// treats those as modifiers. Combos another client already grabbed
+    /// in any of these variants are reported and skipped, with none of
+    /// their variants left grabbed.
+    pub fn grab(x11: &X11Connection, bindings: &[Binding]) -> Result<Self> {
+        let conn = x11.conn();
+        let setup = conn.setup();
+        let (min_keycode, max_keycode) = (setup.min_keycode, setup.max_keycode);
+        let mapping = conn
+            .get_keyboard_mapping(min_keycode, max_keycode - min_keycode + 1)?
+            .reply()?;
+        let per_keycode = mapping.keysyms_per_keycode as usize;
+
+        // Unshifted keysyms first, so Shift+1 grabs the key labelled 1
+        let keycode_of = |keysym: u32| -> Option<u8> {
+            (0..per_keycode).find_map(|column| {
+                mapping
+                    .keysyms
+                    .chunks(per_keycode)
+                    .position(|syms| syms.get(column) == Some(&keysym))
+                    .map(|index| min_keycode + index as u8)
+            })
+        };
+
+        let num_lock = keycode_of(XK_NUM_LOCK)
+            .and_then(|keycode| modifier_of(x11, keycode).transpose())
+            .transpose()?
+            .unwrap_or(u16::from(ModMask::M2));
+        let locks = u16::from(ModMask::LOCK) | num_lock;
+
+        let mut actions = HashMap::new();
+        for binding in bindings {
+            let Some(keycode) = keycode_of(binding.combo.keysym) else {
+                eprintln!("xdeskie: no key produces {}, not binding it", binding.combo);
+                continue;
+            };
+
+            let modifiers = binding.combo.modifiers;
+            let masks = [0, u16::from(ModMask::LOCK), num_lock, locks].map(|lock| modifiers | lock);
+            if !grab_all(x11, keycode, &masks)? {
+                eprintln!(
+                    "xdeskie: cannot bind {}: {} is already grabbed by another client",
+                    binding, binding.combo
+                );
+                continue;
+            }
+
+            actions.insert((keycode, modifiers), binding.action.clone());
+        }
+        conn.flush()?;
+
+        Ok(Self { actions, locks })
+    }
+
+    /// The action bound to a key press, if any.
+    pub fn action(&self, ev: &KeyPressEvent) -> Option<&Action> {
+        let modifiers = u16::from(ev.state) & !self.locks & 0xff;
+        self.actions.get(&(ev.detail, modifiers))
+    }
+}
+
+/// Grab a key with each of `masks`. If another client holds one of them,
+/// release those already grabbed and return false.
+fn grab_all(x11: &X11Connection, keycode: u8, masks: &[u16]) -> Result<bool> {
+    let conn = x11.conn();
+    for (index, &mask) in masks.iter().enumerate() {
+        let grabbed = conn
+            .grab_key(
+                false,
+                x11.root(),
+                ModMask::from(mask),
+                keycode,
+                GrabMode::ASYNC,
+                GrabMode::ASYNC,
+            )?
+            .check();
+        match grabbed {
+            Ok(()) => {}
+            Err(ReplyError::X11Error(_)) => {
+                for &mask in &masks[..index] {
+                    conn.ungrab_key(keycode, x11.root(), ModMask::from(mask))?;
+                }
+                return Ok(false);
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(true)
+}
+
+/// Modifier mask a keycode is mapped to, if any.
+fn modifier_of(x11: &X11Connection, keycode: u8) -> Result<Option<u16>> {
+    let mapping = x11.conn().get_modifier_mapping()?.reply()?;
+    let per_modifier = mapping.keycodes_per_modifier() as usize;
+    Ok(mapping
+        .keycodes
+        .chunks(per_modifier.max(1))
+        .position(|keycodes| keycodes.contains(&keycode))
+        .map(|index| 1 << index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bindings() {
+        let binding: Binding = "Mod4+Shift+1=move sticky".parse().unwrap();
+        assert_eq!(
+            binding,
+            Binding {
+                combo: KeyCombo {
+                    modifiers: 1 << 6 | 1,
+                    keysym: '1' as u32
+                },
+                action: Action::Move("sticky".to_string()),
+            }
+        );
+        assert_eq!(binding.to_string(), "Shift+Mod4+1=move sticky");
+
+        let binding: Binding = "super+ctrl+Right=next".parse().unwrap();
+        assert_eq!(binding.combo.modifiers, 1 << 6 | 1 << 2);
+        assert_eq!(binding.to_string(), "Control+Mod4+Right=next");
+        assert_eq!("Alt+F12=show-desktop".parse::<Binding>().unwrap().combo.keysym, 0xffc9);
+        assert_eq!("Mod4+Q=switch mail".parse::<Binding>().unwrap().combo.keysym, 'q' as u32);
+
+        assert!("Mod4+1".parse::<Binding>().is_err());
+        assert!("Hyper+1=next".parse::<Binding>().is_err());
+        assert!("Mod4+F13=next".parse::<Binding>().is_err());
+        assert!("Mod4+1=switch".parse::<Binding>().is_err());
+        assert!("Mod4+=next".parse::<Binding>().is_err());
+    }
+
+    #[test]
+    fn default_bindings_cover_nine_desktops() {
+        let bindings = default_bindings();
+        assert_eq!(bindings.len(), 18);
+        assert_eq!(bindings[0].to_string(), "Mod4+1=switch 1");
+        assert_eq!(bindings[17].to_string(), "Shift+Mod4+9=move 9");
+    }
+}
@@ -0,0 +1,317 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use x11rb::reexports::x11rb_protocol::parse_display::parse_display;
+
+use crate::backend::Backend;
+use crate::commands::desktop::{apply_switch, format_desktops, record_switch};
+use crate::commands::parse_window_id;
+use crate::commands::window::apply_move_window;
+use crate::state::DesktopState;
+
+/// How long the daemon waits for a client to send its request or take the
+/// response; the CLI does both right away.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long the CLI waits for the daemon's answer before giving up on it.
+const DAEMON_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A command the CLI hands to the daemon, with arguments exactly as typed.
+///
+/// Sent as one JSON object per line, e.g. `{"command":"switch","desktop":"2"}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Request {
+    /// Switch to a desktop (1-indexed or name)
+    Switch {
+        /// Desktop number or name
+        desktop: String,
+    },
+    /// Move a window to a desktop
+    Move {
+        /// Window ID (hex, decimal or "active")
+        window: String,
+        /// Desktop number, name, 0 or "sticky"
+        desktop: String,
+    },
+    /// List desktops
+    List,
+}
+
+/// The daemon's answer to a request, sent as one JSON line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum Response {
+    /// The request succeeded
+    Ok {
+        /// What the command prints when run directly
+        output: String,
+    },
+    /// The request failed
+    Error {
+        /// Why it failed
+        message: String,
+    },
+}
+
+impl Request {
+    /// Carry out the request, returning what it prints.
+    ///
+    /// Used both by the daemon and by the CLI when no daemon is running,
+    /// so the two modes behave and print exactly alike. Names and ranges
+    /// are checked inside the transaction, against state another command
+    /// may just have changed.
+    pub fn execute(&self, backend: &impl Backend, state: &mut DesktopState) -> Result<String> {
+        match self {
+            Request::Switch { desktop } => state.transaction(backend, |state| {
+                let desktop = state.resolve_desktop(desktop)?;
+                if desktop == 0 || desktop > state.desktops {
+                    return Err(anyhow!(
+                        "Invalid desktop {}. Valid range: 1-{}",
+                        desktop,
+                        state.desktops
+                    ));
+                }
+                record_switch(state, desktop - 1);
+                apply_switch(backend, state, desktop - 1)?;
+                Ok(format!("Switched to desktop {}\n", desktop))
+            }),
+            Request::Move { window, desktop } => {
+                let window_id = parse_window_id(window, backend)?;
+                state.transaction(backend, |state| {
+                    let desktop = if desktop.eq_ignore_ascii_case("sticky") {
+                        0
+                    } else {
+                        state.resolve_desktop(desktop)?
+                    };
+                    apply_move_window(backend, state, window_id, desktop)?;
+
+                    if desktop == 0 {
+                        Ok(format!("Window 0x{:x} is now sticky (all desktops)\n", window_id))
+                    } else {
+                        Ok(format!("Moved window 0x{:x} to desktop {}\n", window_id, desktop))
+                    }
+                })
+            }
+            Request::List => state.transaction(backend, |state| Ok(format_desktops(state))),
+        }
+    }
+}
+
+/// Location of the daemon socket for a display and screen, under
+/// `$XDG_RUNTIME_DIR/xdeskie`. None if there is no runtime directory.
+pub fn socket_path(display: &str, screen: usize) -> Option<PathBuf> {
+    let key = format!("{}.{}", display, screen).replace('/', "%2F");
+    Some(dirs::runtime_dir()?.join("xdeskie").join(format!("{}.sock", key)))
+}
+
+/// Location of the daemon socket for `$DISPLAY`, found without connecting
+/// to the X server.
+pub fn display_socket_path() -> Option<PathBuf> {
+    let parsed = parse_display(None).ok()?;
+    socket_path(&format!("{}:{}", parsed.host, parsed.display), parsed.screen as usize)
+}
+
+/// Send a request to the daemon listening at `path`.
+///
+/// Returns None when no daemon is listening, or when it does not answer
+/// within `DAEMON_TIMEOUT`, so the caller can run the command itself.
+pub fn forward(path: &Path, request: &Request) -> Result<Option<Response>> {
+    forward_within(path, request, DAEMON_TIMEOUT)
+}
+
+fn forward_within(path: &Path, request: &Request, timeout: Duration) -> Result<Option<Response>> {
+    let Ok(stream) = UnixStream::connect(path) else {
+        return Ok(None);
+    };
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    let mut reply = String::new();
+    let exchanged = (&stream)
+        .write_all(line.as_bytes())
+        .and_then(|()| BufReader::new(&stream).read_line(&mut reply));
+    match exchanged {
+        Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+            eprintln!("xdeskie: the daemon is not answering; running the command directly");
+            return Ok(None);
+        }
+        result => result?,
+    };
+
+    if reply.is_empty() {
+        return Err(anyhow!("xdeskie daemon closed the connection without answering"));
+    }
+    Ok(Some(serde_json::from_str(&reply)?))
+}
+
+/// The daemon's listening socket. The socket file is removed when dropped.
+pub struct Server {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl Server {
+    /// Listen at `path`, replacing a socket left behind by a daemon that
+    /// is no longer running.
+    pub fn bind(path: &Path) -> Result<Self> {
+        if UnixStream::connect(path).is_ok() {
+            return Err(anyhow!("An xdeskie daemon is already listening on {}", path.display()));
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+
+        let listener = UnixListener::bind(path)?;
+        Ok(Self {
+            listener,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Answer requests one connection at a time until `stop` returns true.
+    /// A client that sends nothing is dropped after `CLIENT_TIMEOUT`.
+    ///
+    /// `stop` is checked after every connection; call `wake` after making
+    /// it true.
+    pub fn serve(&self, mut handle: impl FnMut(Request) -> Response, stop: impl Fn() -> bool) {
+        for stream in self.listener.incoming() {
+            if stop() {
+                break;
+            }
+            let result = stream
+                .map_err(anyhow::Error::from)
+                .and_then(|stream| answer(stream, &mut handle));
+            if let Err(e) = result {
+                eprintln!("xdeskie: IPC request failed: {}", e);
+            }
+        }
+    }
+
+    /// Make `serve` check its stop condition.
+    pub fn wake(&self) {
+        let _ = UnixStream::connect(&self.path);
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Read one request line from a client and write back the response.
+fn answer(stream: UnixStream, handle: &mut impl FnMut(Request) -> Response) -> Result<()> {
+    // Clients are answered in turn, so a stuck one must not hold up the rest
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    if line.is_empty() {
+        return Ok(());
+    }
+
+    let response = match serde_json::from_str(&line) {
+        Ok(request) => handle(request),
+        Err(e) => Response::Error {
+            message: format!("Invalid request: {}", e),
+        },
+    };
+
+    let mut reply = serde_json::to_string(&response)?;
+    reply.push('\n');
+    (&stream).write_all(reply.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn requests_are_json_lines() {
+        let request = Request::Move {
+            window: "active".to_string(),
+            desktop: "sticky".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_string(&request).unwrap(),
+            r#"{"command":"move","window":"active","desktop":"sticky"}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Request>(r#"{"command":"list"}"#).unwrap(),
+            Request::List
+        );
+    }
+
+    #[test]
+    fn forwards_requests_to_the_server() {
+        let dir = std::env::temp_dir().join(format!("xdeskie-ipc-test-{}", std::process::id()));
+        let path = dir.join("test.sock");
+        assert!(forward(&path, &Request::List).unwrap().is_none(), "no daemon yet");
+
+        let server = Server::bind(&path).unwrap();
+        assert!(Server::bind(&path).is_err(), "already listening");
+
+        let served = AtomicUsize::new(0);
+        let silent = UnixStream::connect(&path).unwrap();
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                server.serve(
+                    |request| {
+                        served.fetch_add(1, Ordering::SeqCst);
+                        Response::Ok {
+                            output: format!("{:?}", request),
+                        }
+                    },
+                    || served.load(Ordering::SeqCst) == 2,
+                )
+            });
+
+            let switch = Request::Switch {
+                desktop: "web".to_string(),
+            };
+            assert_eq!(
+                forward(&path, &switch).unwrap(),
+                Some(Response::Ok {
+                    output: format!("{:?}", switch)
+                })
+            );
+            assert!(forward(&path, &Request::List).unwrap().is_some());
+            server.wake();
+        });
+        drop(silent);
+
+        drop(server);
+        assert!(!path.exists());
+        let _ = fs::remove_dir(&dir);
+    }
+
+    #[test]
+    fn a_stuck_daemon_is_bypassed() {
+        let dir = std::env::temp_dir().join(format!("xdeskie-ipc-stuck-test-{}", std::process::id()));
+        let path = dir.join("test.sock");
+        // Accepts connections but never answers them
+        let server = Server::bind(&path).unwrap();
+
+        let response = forward_within(&path, &Request::List, Duration::from_millis(100)).unwrap();
+        assert!(response.is_none());
+
+        drop(server);
+        let _ = fs::remove_dir(&dir);
+    }
+}
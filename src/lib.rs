@@ -0,0 +1,81 @@
+//! Virtual desktops for TWM and other X11 window managers without EWMH
+//! desktop support.
+//!
+//! Desktops are implemented by mapping and unmapping toplevel windows.
+//! Assignments are stored as properties on each window and in a per-display
+//! state file, so every process using this crate sees the same layout.
+//!
+//! The `xdeskie` binary is a thin command-line client of this library;
+//! status bars, launchers and other tools can call the same operations
+//! directly:
+//!
+//! ```no_run
+//! use xdeskie::commands::desktop::switch_to_desktop;
+//! use xdeskie::commands::window::move_window;
+//! use xdeskie::{Backend, DesktopState, X11Connection};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let x11 = X11Connection::new()?;
+//! let mut state = DesktopState::load(&x11)?;
+//! state.sync_from_x(&x11)?;
+//!
+//! // Desktops are 0-indexed here, as in `DesktopState::current`
+//! switch_to_desktop(&x11, &mut state, 1)?;
+//!
+//! // Window assignments are 1-indexed, with 0 meaning sticky
+//! let active = x11.get_active_window()?;
+//! move_window(&x11, &mut state, active, 0)?;
+//!
+//! println!("now on desktop {}", state.desktop_label(state.current));
+//! # Ok(())
+//! # }
+//! ```
+
+#![warn(missing_docs)]
+
+// Only desktop and window operations, the state they work on and the
+// backend they drive are public API. The rest is exported solely for the
+// `xdeskie` binary and may change without notice.
+
+/// Window system abstraction
+mod backend;
+/// Desktop and window operations
+pub mod commands;
+/// User configuration file
+mod config;
+/// Resident mode handling EWMH requests from other tools
+#[doc(hidden)]
+pub mod daemon;
+/// EWMH desktop properties for pagers and panels
+mod ewmh;
+/// Watching desktops and windows for changes
+#[doc(hidden)]
+pub mod events;
+/// Recognizing applications across restarts
+mod fingerprint;
+/// Undo/redo snapshots
+mod history;
+/// Global key bindings handled by the daemon
+#[doc(hidden)]
+pub mod hotkeys;
+/// Unix socket protocol between the CLI and the daemon
+#[doc(hidden)]
+pub mod ipc;
+/// Persistent pager toolbar
+#[doc(hidden)]
+pub mod pager;
+/// Desktop identification popup
+#[doc(hidden)]
+pub mod popup;
+/// Saved application sessions
+mod session;
+/// Persistent desktop state
+mod state;
+/// X11 connection and window queries
+mod x11;
+
+pub use backend::Backend;
+#[doc(hidden)]
+pub use config::Config;
+pub use state::DesktopState;
+pub use x11::{WindowInfo, WmState, X11Connection};
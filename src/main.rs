@@ -1,111 +1,228 @@
 mod cli;
-mod commands;
-mod pager;
-mod popup;
-mod state;
-mod x11;
+
+use std::time::Duration;
 
 use anyhow::{anyhow, Result};
 use clap::Parser;
 
-use cli::{Args, Command};
-use commands::{
-    list_desktops, list_windows, move_window, parse_window_id, print_current_desktop,
-    set_desktop_count, switch_to_desktop,
+use cli::{Args, Command, ConfigAction, SessionAction, ShowDesktopMode, StateAction, SubscribeEvent};
+use xdeskie::commands::{
+    check_config, dump_config, list_states, list_windows, print_current_desktop, prune_states, redo, rename_desktop,
+    restore_session, save_session, set_desktop_count, show_desktop, undo,
 };
-use commands::desktop::{switch_next, switch_prev};
-use state::DesktopState;
-use x11::X11Connection;
+use xdeskie::commands::desktop::{switch_next, switch_prev};
+use xdeskie::events::{self, EventKind};
+use xdeskie::hotkeys::{default_bindings, Binding};
+use xdeskie::ipc::{self, Request, Response};
+use xdeskie::{daemon, pager, popup, Config, DesktopState, X11Connection};
 
 fn main() -> Result<()> {
     let args = Args::parse();
+
+    // State housekeeping must work without a running X server
+    if let Command::State { action } = &args.command {
+        return handle_state(action);
+    }
+    if let Command::Config { action } = &args.command {
+        return handle_config(action);
+    }
+
+    // A running daemon already holds a connection and fresh state
+    if let Some(request) = forwardable(&args.command) {
+        if let Some(response) = forward(&request)? {
+            return print_response(response);
+        }
+    }
+
     let x11 = X11Connection::new()?;
-    let mut state = DesktopState::load()?;
+    let mut state = DesktopState::load(&x11)?;
 
     state.sync_from_x(&x11)?;
 
-    run_command(args.command, &x11, &mut state)
+    let config = Config::load_or_default();
+    run_command(args.command, &x11, &mut state, &config)
+}
+
+/// The daemon request for a command it can serve, if any.
+fn forwardable(command: &Command) -> Option<Request> {
+    match command {
+        Command::Switch { desktop } => Some(Request::Switch {
+            desktop: desktop.clone(),
+        }),
+        Command::Move { window, desktop } => Some(Request::Move {
+            window: window.clone(),
+            desktop: desktop.clone(),
+        }),
+        Command::List => Some(Request::List),
+        _ => None,
+    }
+}
+
+/// Hand a command to the daemon for this display, if one is running.
+fn forward(request: &Request) -> Result<Option<Response>> {
+    match ipc::display_socket_path() {
+        Some(path) => ipc::forward(&path, request),
+        None => Ok(None),
+    }
+}
+
+fn print_response(response: Response) -> Result<()> {
+    match response {
+        Response::Ok { output } => {
+            print!("{}", output);
+            Ok(())
+        }
+        Response::Error { message } => Err(anyhow!(message)),
+    }
+}
+
+/// Run a command the daemon could have served, in this process.
+fn execute(request: Request, x11: &X11Connection, state: &mut DesktopState) -> Result<()> {
+    print!("{}", request.execute(x11, state)?);
+    Ok(())
 }
 
-fn run_command(command: Command, x11: &X11Connection, state: &mut DesktopState) -> Result<()> {
+fn run_command(command: Command, x11: &X11Connection, state: &mut DesktopState, config: &Config) -> Result<()> {
     match command {
-        Command::Switch { desktop } => handle_switch(x11, state, desktop),
-        Command::Next => handle_next(x11, state),
-        Command::Prev => handle_prev(x11, state),
-        Command::Move { window, desktop } => handle_move(x11, state, &window, desktop),
+        Command::Switch { desktop } => execute(Request::Switch { desktop }, x11, state),
+        Command::Next => handle_next(x11, state, config),
+        Command::Prev => handle_prev(x11, state, config),
+        Command::Move { window, desktop } => execute(Request::Move { window, desktop }, x11, state),
         Command::SetDesktops { count } => handle_set_desktops(x11, state, count),
-        Command::List => {
-            list_desktops(state);
+        Command::Rename { desktop, name } => handle_rename(x11, state, &desktop, &name),
+        Command::ShowDesktop { mode } => handle_show_desktop(x11, state, mode),
+        Command::Undo => {
+            println!("Undid: {}", undo(x11, state)?);
+            Ok(())
+        }
+        Command::Redo => {
+            println!("Redid: {}", redo(x11, state)?);
             Ok(())
         }
+        Command::List => execute(Request::List, x11, state),
         Command::Current => {
             print_current_desktop(state);
             Ok(())
         }
         Command::Windows => list_windows(x11, state),
-        Command::Identify => handle_identify(x11, state),
-        Command::Gui => handle_gui(x11, state),
+        Command::Identify => handle_identify(x11, state, config),
+        Command::Gui => handle_gui(x11, state, config),
+        Command::Daemon { default_keys, bindings } => handle_daemon(x11, state, config, default_keys, bindings),
+        Command::Subscribe { events } => handle_subscribe(x11, state, &events),
+        Command::Session { action } => handle_session(x11, state, action),
+        Command::State { action } => handle_state(&action),
+        Command::Config { action } => handle_config(&action),
     }
 }
 
-fn handle_switch(x11: &X11Connection, state: &mut DesktopState, desktop: u32) -> Result<()> {
-    if desktop == 0 || desktop > state.desktops {
-        return Err(anyhow!(
-            "Invalid desktop {}. Valid range: 1-{}",
-            desktop,
-            state.desktops
-        ));
-    }
-
-    let target = desktop - 1;
-    switch_to_desktop(x11, state, target)?;
-    println!("Switched to desktop {}", desktop);
-
+fn handle_next(x11: &X11Connection, state: &mut DesktopState, config: &Config) -> Result<()> {
+    let next = switch_next(x11, state, config.desktops.wrap)?;
+    println!("Switched to desktop {}", next + 1);
     Ok(())
 }
 
-fn handle_next(x11: &X11Connection, state: &mut DesktopState) -> Result<()> {
-    let next = switch_next(x11, state)?;
-    println!("Switched to desktop {}", next + 1);
+fn handle_prev(x11: &X11Connection, state: &mut DesktopState, config: &Config) -> Result<()> {
+    let prev = switch_prev(x11, state, config.desktops.wrap)?;
+    println!("Switched to desktop {}", prev + 1);
     Ok(())
 }
 
-fn handle_prev(x11: &X11Connection, state: &mut DesktopState) -> Result<()> {
-    let prev = switch_prev(x11, state)?;
-    println!("Switched to desktop {}", prev + 1);
+fn handle_set_desktops(x11: &X11Connection, state: &mut DesktopState, count: u32) -> Result<()> {
+    set_desktop_count(x11, state, count)?;
+    println!("Set desktop count to {}", count);
     Ok(())
 }
 
-fn handle_move(
-    x11: &X11Connection,
-    state: &mut DesktopState,
-    window: &str,
-    desktop: u32,
-) -> Result<()> {
-    let window_id = parse_window_id(window, x11)?;
-    move_window(x11, state, window_id, desktop)?;
+fn handle_rename(x11: &X11Connection, state: &mut DesktopState, desktop: &str, name: &str) -> Result<()> {
+    let desktop = state.resolve_desktop(desktop)?;
+    rename_desktop(x11, state, desktop, name)?;
 
-    if desktop == 0 {
-        println!("Window 0x{:x} is now sticky (all desktops)", window_id);
+    if name.is_empty() {
+        println!("Removed name of desktop {}", desktop);
     } else {
-        println!("Moved window 0x{:x} to desktop {}", window_id, desktop);
+        println!("Renamed desktop {} to {}", desktop, name);
     }
 
     Ok(())
 }
 
-fn handle_set_desktops(x11: &X11Connection, state: &mut DesktopState, count: u32) -> Result<()> {
-    set_desktop_count(x11, state, count)?;
-    println!("Set desktop count to {}", count);
+fn handle_show_desktop(x11: &X11Connection, state: &mut DesktopState, mode: ShowDesktopMode) -> Result<()> {
+    let show = match mode {
+        ShowDesktopMode::On => Some(true),
+        ShowDesktopMode::Off => Some(false),
+        ShowDesktopMode::Toggle => None,
+    };
+
+    if show_desktop(x11, state, show)? {
+        println!("Showing desktop");
+    } else {
+        println!("Restored windows");
+    }
+
     Ok(())
 }
 
-fn handle_identify(x11: &X11Connection, state: &DesktopState) -> Result<()> {
-    popup::show_desktop_popup(x11, state.current)?;
+fn handle_identify(x11: &X11Connection, state: &DesktopState, config: &Config) -> Result<()> {
+    popup::show_desktop_popup(x11, &state.desktop_label(state.current), &config.popup)?;
     Ok(())
 }
 
-fn handle_gui(x11: &X11Connection, state: &mut DesktopState) -> Result<()> {
+fn handle_gui(x11: &X11Connection, state: &mut DesktopState, config: &Config) -> Result<()> {
     // Run pager as persistent toolbar (runs forever until killed)
-    pager::run_pager(x11, state)
+    pager::run_pager(x11, state, &config.pager)
+}
+
+fn handle_daemon(
+    x11: &X11Connection,
+    state: &mut DesktopState,
+    config: &Config,
+    default_keys: bool,
+    bindings: Vec<Binding>,
+) -> Result<()> {
+    // Later bindings replace earlier ones for the same keys: defaults, then
+    // the config file, then --bind
+    let mut all = if default_keys && !config.keys.defaults {
+        default_bindings()
+    } else {
+        Vec::new()
+    };
+    all.extend(config.keys.bindings()?);
+    all.extend(bindings);
+    daemon::run_daemon(x11, state, config, &all)
+}
+
+fn handle_subscribe(x11: &X11Connection, state: &mut DesktopState, events: &[SubscribeEvent]) -> Result<()> {
+    let kinds: Vec<EventKind> = events
+        .iter()
+        .map(|event| match event {
+            SubscribeEvent::Desktop => EventKind::Desktop,
+            SubscribeEvent::Window => EventKind::Window,
+            SubscribeEvent::Count => EventKind::Count,
+            SubscribeEvent::Urgent => EventKind::Urgent,
+        })
+        .collect();
+    events::subscribe(x11, state, &kinds)
+}
+
+fn handle_session(x11: &X11Connection, state: &mut DesktopState, action: SessionAction) -> Result<()> {
+    match action {
+        SessionAction::Save { name } => save_session(x11, state, &name),
+        SessionAction::Restore { name, timeout } => {
+            restore_session(x11, state, &name, Duration::from_secs(timeout))
+        }
+    }
+}
+
+fn handle_state(action: &StateAction) -> Result<()> {
+    match action {
+        StateAction::List => list_states(),
+        StateAction::Prune => prune_states(),
+    }
+}
+
+fn handle_config(action: &ConfigAction) -> Result<()> {
+    match action {
+        ConfigAction::Check => check_config(),
+        ConfigAction::Dump => dump_config(),
+    }
 }
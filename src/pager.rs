@@ -2,7 +2,7 @@ use anyhow::Result;
 use x11rb::connection::Connection;
 use x11rb::protocol::xproto::{
     Atom, ButtonPressEvent, ConfigureNotifyEvent, ConfigureWindowAux, ConnectionExt, CreateGCAux,
-    CreateWindowAux, EventMask, ExposeEvent, Gcontext, PropertyNotifyEvent, Rectangle, StackMode,
+    CreateWindowAux, EventMask, ExposeEvent, Gcontext, Rectangle, StackMode,
     Window, WindowClass,
 };
 use x11rb::protocol::Event;
@@ -10,22 +10,15 @@ use x11rb::wrapper::ConnectionExt as WrapperConnectionExt;
 use x11rb::COPY_DEPTH_FROM_PARENT;
 
 use crate::commands::{move_window, switch_to_desktop};
+use crate::config::PagerConfig;
+use crate::events::{EventKind, Watcher};
+use crate::ewmh::NET_DESKTOP_NAMES;
 use crate::state::DesktopState;
-use crate::x11::X11Connection;
+use crate::x11::{latin1, X11Connection};
 
-const DEFAULT_CELL_SIZE: u16 = 32;
-const PADDING: u16 = 4;
-const BORDER: u16 = 2;
 const MIN_CELL_SIZE: u16 = 16;
 
-const PROP_CURRENT: &[u8] = b"_XDESKIE_CURRENT_DESKTOP";
-
-// X11 mouse buttons
 const BUTTON_LEFT: u8 = 1;
-const BUTTON_RIGHT: u8 = 3;
-const BUTTON_SCROLL_UP: u8 = 4;
-const BUTTON_SCROLL_DOWN: u8 = 5;
-
 
 /// Holds the pager window state for recreation
 struct PagerWindow {
@@ -41,15 +34,15 @@ struct PagerWindow {
 fn create_pager_window(
     conn: &impl Connection,
     root: Window,
-    screen_width: u16,
-    screen_height: u16,
+    (screen_width, screen_height): (u16, u16),
     white_pixel: u32,
     black_pixel: u32,
     num_desktops: u32,
+    config: &PagerConfig,
 ) -> Result<PagerWindow> {
     // Calculate initial window size
-    let win_width = num_desktops as u16 * (DEFAULT_CELL_SIZE + PADDING) + PADDING;
-    let win_height = DEFAULT_CELL_SIZE + PADDING * 2;
+    let win_width = num_desktops as u16 * (config.cell_size + config.padding) + config.padding;
+    let win_height = config.cell_size + config.padding * 2;
 
     // Position at bottom center
     let x = (screen_width.saturating_sub(win_width)) / 2;
@@ -68,7 +61,7 @@ fn create_pager_window(
         y as i16,
         win_width,
         win_height,
-        BORDER,
+        config.border,
         WindowClass::INPUT_OUTPUT,
         0,
         &CreateWindowAux::new()
@@ -128,147 +121,154 @@ fn create_pager_window(
     })
 }
 
-/// Run the pager as a persistent floating toolbar.
+/// Run the pager as a persistent floating toolbar, sized and driven by
+/// the `[pager]` config section.
 /// This function runs indefinitely until the process is killed.
 /// If the window is destroyed externally, it will be automatically recreated.
-pub fn run_pager(x11: &X11Connection, state: &mut DesktopState) -> Result<()> {
+pub fn run_pager(x11: &X11Connection, state: &mut DesktopState, config: &PagerConfig) -> Result<()> {
     let conn = x11.conn();
     let root = x11.root();
-    let (screen_width, screen_height) = x11.screen_size();
+    let screen_size = x11.screen_size();
     let (white_pixel, black_pixel) = x11.screen_pixels();
 
     let num_desktops = state.desktops;
     let mut current = state.current;
 
-    // Subscribe to property changes on root window to detect desktop switches
-    conn.change_window_attributes(
-        root,
-        &x11rb::protocol::xproto::ChangeWindowAttributesAux::new()
-            .event_mask(EventMask::PROPERTY_CHANGE),
-    )?;
-
-    // Get the atom for desktop property
-    let current_atom = conn.intern_atom(false, PROP_CURRENT)?.reply()?.atom;
+    // Watch root window properties to detect desktop switches
+    let mut watcher = Watcher::new(x11, state, &[EventKind::Desktop])?;
+    let desktop_names = conn.intern_atom(false, NET_DESKTOP_NAMES)?.reply()?.atom;
 
     // Create initial window
-    let mut pager = create_pager_window(conn, root, screen_width, screen_height, white_pixel, black_pixel, num_desktops)?;
+    let mut pager = create_pager_window(conn, root, screen_size, white_pixel, black_pixel, num_desktops, config)?;
 
     // Draw initial state
-    draw_pager(conn, pager.win_id, pager.gc_id, pager.gc_inv_id, num_desktops, current, pager.win_width, pager.win_height)?;
+    draw_pager(conn, &pager, state, num_desktops, current, config)?;
 
     // Event loop - runs forever
     loop {
-        let event = conn.wait_for_event()?;
-        match event {
-            Event::Expose(ExposeEvent { window, count: 0, .. }) if window == pager.win_id => {
-                draw_pager(conn, pager.win_id, pager.gc_id, pager.gc_inv_id, num_desktops, current, pager.win_width, pager.win_height)?;
-            }
-            Event::ConfigureNotify(ConfigureNotifyEvent { window, width, height, .. }) if window == pager.win_id => {
-                // Window was resized
-                if width != pager.win_width || height != pager.win_height {
+        // Handle everything queued before rereading desktop state once
+        let mut event = conn.wait_for_event()?;
+        let mut names_changed = false;
+        loop {
+            match event {
+                Event::Expose(ExposeEvent { window, count: 0, .. }) if window == pager.win_id => {
+                    draw_pager(conn, &pager, state, num_desktops, current, config)?;
+                }
+                Event::ConfigureNotify(ConfigureNotifyEvent { window, width, height, .. })
+                    if window == pager.win_id
+                        && (width != pager.win_width || height != pager.win_height) =>
+                {
+                    // Window was resized
                     pager.win_width = width;
                     pager.win_height = height;
-                    draw_pager(conn, pager.win_id, pager.gc_id, pager.gc_inv_id, num_desktops, current, pager.win_width, pager.win_height)?;
+                    draw_pager(conn, &pager, state, num_desktops, current, config)?;
                 }
-            }
-            Event::DestroyNotify(ev) if ev.window == pager.win_id => {
-                // Window was destroyed externally - recreate it
-                eprintln!("xdeskie: pager window destroyed, recreating...");
-                pager = create_pager_window(conn, root, screen_width, screen_height, white_pixel, black_pixel, num_desktops)?;
-                draw_pager(conn, pager.win_id, pager.gc_id, pager.gc_inv_id, num_desktops, current, pager.win_width, pager.win_height)?;
-            }
-            Event::UnmapNotify(ev) if ev.window == pager.win_id => {
-                // Window was unmapped - remap it to keep it visible
-                conn.map_window(pager.win_id)?;
-                conn.flush()?;
-            }
-            Event::ButtonPress(ev) if ev.event == pager.win_id => {
-                match ev.detail {
-                    BUTTON_LEFT => {
-                        // Left click - switch to clicked desktop
-                        if let Some(target) = get_clicked_desktop(&ev, num_desktops, pager.win_width, pager.win_height) {
-                            if target != current {
-                                switch_to_desktop(x11, state, target)?;
-                                current = target;
-                                // Raise pager to stay on top of newly visible windows
-                                conn.configure_window(pager.win_id, &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE))?;
-                                draw_pager(conn, pager.win_id, pager.gc_id, pager.gc_inv_id, num_desktops, current, pager.win_width, pager.win_height)?;
+                Event::DestroyNotify(ev) if ev.window == pager.win_id => {
+                    // Window was destroyed externally - recreate it
+                    eprintln!("xdeskie: pager window destroyed, recreating...");
+                    pager = create_pager_window(conn, root, screen_size, white_pixel, black_pixel, num_desktops, config)?;
+                    draw_pager(conn, &pager, state, num_desktops, current, config)?;
+                }
+                Event::UnmapNotify(ev) if ev.window == pager.win_id => {
+                    // Window was unmapped - remap it to keep it visible
+                    conn.map_window(pager.win_id)?;
+                    conn.flush()?;
+                }
+                Event::ButtonPress(ev) if ev.event == pager.win_id => {
+                    let buttons = &config.buttons;
+                    match ev.detail {
+                        button if button == buttons.switch => {
+                            // Switch to clicked desktop
+                            if let Some(target) = get_clicked_desktop(&ev, num_desktops, pager.win_width, pager.win_height, config) {
+                                if target != current {
+                                    switch_to_desktop(x11, state, target)?;
+                                    current = target;
+                                    // Raise pager to stay on top of newly visible windows
+                                    conn.configure_window(pager.win_id, &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE))?;
+                                    draw_pager(conn, &pager, state, num_desktops, current, config)?;
+                                }
                             }
                         }
-                    }
-                    BUTTON_RIGHT => {
-                        // Right click - grab pointer and let user click a window to move to this desktop
-                        if let Some(target) = get_clicked_desktop(&ev, num_desktops, pager.win_width, pager.win_height) {
-                            if let Ok(Some(window_id)) = grab_window_pick(x11) {
-                                // Move the selected window to the target desktop (1-indexed for move_window)
-                                if let Err(e) = move_window(x11, state, window_id, target + 1) {
-                                    eprintln!("xdeskie: failed to move window: {}", e);
+                        button if button == buttons.move_window => {
+                            // Grab pointer and let user click a window to move to this desktop
+                            if let Some(target) = get_clicked_desktop(&ev, num_desktops, pager.win_width, pager.win_height, config) {
+                                if let Ok(Some(window_id)) = grab_window_pick(x11) {
+                                    // Move the selected window to the target desktop (1-indexed for move_window)
+                                    if let Err(e) = move_window(x11, state, window_id, target + 1) {
+                                        eprintln!("xdeskie: failed to move window: {}", e);
+                                    }
                                 }
+                                // Redraw pager in case we need to refresh
+                                draw_pager(conn, &pager, state, num_desktops, current, config)?;
                             }
-                            // Redraw pager in case we need to refresh
-                            draw_pager(conn, pager.win_id, pager.gc_id, pager.gc_inv_id, num_desktops, current, pager.win_width, pager.win_height)?;
-                        }
-                    }
-                    BUTTON_SCROLL_UP => {
-                        // Scroll up - previous desktop (no wrap)
-                        if current > 0 {
-                            let prev = current - 1;
-                            switch_to_desktop(x11, state, prev)?;
-                            current = prev;
-                            conn.configure_window(pager.win_id, &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE))?;
-                            draw_pager(conn, pager.win_id, pager.gc_id, pager.gc_inv_id, num_desktops, current, pager.win_width, pager.win_height)?;
                         }
-                    }
-                    BUTTON_SCROLL_DOWN => {
-                        // Scroll down - next desktop (no wrap)
-                        if current < num_desktops - 1 {
-                            let next = current + 1;
-                            switch_to_desktop(x11, state, next)?;
-                            current = next;
-                            conn.configure_window(pager.win_id, &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE))?;
-                            draw_pager(conn, pager.win_id, pager.gc_id, pager.gc_inv_id, num_desktops, current, pager.win_width, pager.win_height)?;
+                        button if button == buttons.previous || button == buttons.next => {
+                            // Scroll - neighbouring desktop, wrapping only if configured
+                            let forward = button == buttons.next;
+                            if let Some(target) = scroll_target(current, num_desktops, forward, config.scroll_wraps) {
+                                switch_to_desktop(x11, state, target)?;
+                                current = target;
+                                conn.configure_window(pager.win_id, &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE))?;
+                                draw_pager(conn, &pager, state, num_desktops, current, config)?;
+                            }
                         }
+                        _ => {}
                     }
-                    _ => {}
                 }
-            }
-            Event::PropertyNotify(PropertyNotifyEvent { atom, .. }) if atom == current_atom => {
-                // Desktop changed externally, update display
-                if let Some(new_current) = x11.get_root_property(PROP_CURRENT)? {
-                    if new_current != current {
-                        current = new_current;
-                        state.current = current;
-                        conn.configure_window(pager.win_id, &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE))?;
-                        draw_pager(conn, pager.win_id, pager.gc_id, pager.gc_inv_id, num_desktops, current, pager.win_width, pager.win_height)?;
-                    }
+                Event::PropertyNotify(ev) if ev.window == root && ev.atom == desktop_names => {
+                    names_changed = true;
                 }
-            }
-            Event::ClientMessage(ev) if ev.window == pager.win_id => {
-                // Check for WM_DELETE_WINDOW
-                if ev.format == 32 && ev.data.as_data32()[0] == pager.wm_delete_window {
+                Event::PropertyNotify(_) => watcher.notice(x11, &event)?,
+                Event::ClientMessage(ev)
+                    if ev.window == pager.win_id
+                        && ev.format == 32
+                        && ev.data.as_data32()[0] == pager.wm_delete_window =>
+                {
                     // User clicked close button - exit gracefully
                     conn.destroy_window(pager.win_id)?;
                     conn.flush()?;
                     return Ok(());
                 }
+                _ => {}
+            }
+            match conn.poll_for_event()? {
+                Some(next) => event = next,
+                None => break,
+            }
+        }
+
+        // Desktop changed externally, update display
+        let changes = watcher.changes(x11, state)?;
+        if !changes.is_empty() && state.current != current {
+            current = state.current;
+            conn.configure_window(pager.win_id, &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE))?;
+            draw_pager(conn, &pager, state, num_desktops, current, config)?;
+        }
+
+        // Desktops were renamed; names are kept in the state file
+        if names_changed {
+            let names = DesktopState::load(x11)?.names;
+            if names != state.names {
+                state.names = names;
+                draw_pager(conn, &pager, state, num_desktops, current, config)?;
             }
-            _ => {}
         }
     }
 }
 
 fn draw_pager(
     conn: &impl Connection,
-    win_id: Window,
-    gc_id: Gcontext,
-    gc_inv_id: Gcontext,
+    pager: &PagerWindow,
+    state: &DesktopState,
     num_desktops: u32,
     current: u32,
-    win_width: u16,
-    win_height: u16,
+    config: &PagerConfig,
 ) -> Result<()> {
+    let padding = config.padding;
+    let PagerWindow { win_id, gc_id, gc_inv_id, win_width, win_height, .. } = *pager;
+
     // Calculate cell dimensions based on window size
-    let (cell_width, cell_height) = calculate_cell_dimensions(num_desktops, win_width, win_height);
+    let (cell_width, cell_height) = calculate_cell_dimensions(num_desktops, win_width, win_height, padding);
 
     // Clear window with white background
     let clear_rect = Rectangle {
@@ -280,13 +280,13 @@ fn draw_pager(
     conn.poly_fill_rectangle(win_id, gc_inv_id, &[clear_rect])?;
 
     // Calculate starting position to center cells
-    let total_cells_width = num_desktops as u16 * (cell_width + PADDING) - PADDING;
+    let total_cells_width = num_desktops as u16 * (cell_width + padding) - padding;
     let start_x = (win_width.saturating_sub(total_cells_width)) / 2;
-    let start_y = PADDING;
+    let start_y = padding;
 
     // Draw each desktop cell
     for i in 0..num_desktops {
-        let cell_x = start_x + i as u16 * (cell_width + PADDING);
+        let cell_x = start_x + i as u16 * (cell_width + padding);
         let cell_y = start_y;
         let is_current = i == current;
 
@@ -315,42 +315,51 @@ fn draw_pager(
         };
         conn.poly_rectangle(win_id, gc_id, &[border])?;
 
-        // Draw desktop number (1-indexed for display)
-        let text = format!("{}", i + 1);
+        // Draw desktop name or number, truncated to fit the cell
         let char_width = 6i16;
         let char_height = 13i16;
+        let max_chars = (cell_width as i16 / char_width).max(1) as usize;
+        let label = latin1(&state.desktop_label(i));
+        let text = &label[..label.len().min(max_chars)];
         let text_width = text.len() as i16 * char_width;
         let text_x = cell_x as i16 + (cell_width as i16 - text_width) / 2;
         let text_y = cell_y as i16 + (cell_height as i16 + char_height) / 2;
 
-        conn.image_text8(win_id, text_gc, text_x, text_y, text.as_bytes())?;
+        conn.image_text8(win_id, text_gc, text_x, text_y, text)?;
     }
 
     conn.flush()?;
     Ok(())
 }
 
-fn calculate_cell_dimensions(num_desktops: u32, win_width: u16, win_height: u16) -> (u16, u16) {
+fn calculate_cell_dimensions(num_desktops: u32, win_width: u16, win_height: u16, padding: u16) -> (u16, u16) {
     // Calculate cell width to fill horizontally
-    let available_width = win_width.saturating_sub(PADDING);
-    let cell_width = (available_width / num_desktops as u16).saturating_sub(PADDING).max(MIN_CELL_SIZE);
+    let available_width = win_width.saturating_sub(padding);
+    let cell_width = (available_width / num_desktops as u16).saturating_sub(padding).max(MIN_CELL_SIZE);
 
     // Use full height minus padding
-    let cell_height = win_height.saturating_sub(PADDING * 2).max(MIN_CELL_SIZE);
+    let cell_height = win_height.saturating_sub(padding * 2).max(MIN_CELL_SIZE);
 
     (cell_width, cell_height)
 }
 
-fn get_clicked_desktop(ev: &ButtonPressEvent, num_desktops: u32, win_width: u16, win_height: u16) -> Option<u32> {
+fn get_clicked_desktop(
+    ev: &ButtonPressEvent,
+    num_desktops: u32,
+    win_width: u16,
+    win_height: u16,
+    config: &PagerConfig,
+) -> Option<u32> {
     let x = ev.event_x as u16;
     let y = ev.event_y as u16;
+    let padding = config.padding;
 
-    let (cell_width, cell_height) = calculate_cell_dimensions(num_desktops, win_width, win_height);
+    let (cell_width, cell_height) = calculate_cell_dimensions(num_desktops, win_width, win_height, padding);
 
     // Calculate starting position (same as draw_pager)
-    let total_cells_width = num_desktops as u16 * (cell_width + PADDING) - PADDING;
+    let total_cells_width = num_desktops as u16 * (cell_width + padding) - padding;
     let start_x = (win_width.saturating_sub(total_cells_width)) / 2;
-    let start_y = PADDING;
+    let start_y = padding;
 
     // Check if click is within cell area vertically
     if y < start_y || y >= start_y + cell_height {
@@ -359,7 +368,7 @@ fn get_clicked_desktop(ev: &ButtonPressEvent, num_desktops: u32, win_width: u16,
 
     // Find which cell was clicked
     for i in 0..num_desktops {
-        let cell_x = start_x + i as u16 * (cell_width + PADDING);
+        let cell_x = start_x + i as u16 * (cell_width + padding);
         if x >= cell_x && x < cell_x + cell_width {
             return Some(i);
         }
@@ -368,6 +377,18 @@ fn get_clicked_desktop(ev: &ButtonPressEvent, num_desktops: u32, win_width: u16,
     None
 }
 
+/// Desktop the mouse wheel leads to from `current`, if any.
+fn scroll_target(current: u32, num_desktops: u32, forward: bool, wraps: bool) -> Option<u32> {
+    match (forward, wraps) {
+        _ if num_desktops < 2 => None,
+        (true, _) if current + 1 < num_desktops => Some(current + 1),
+        (false, _) if current > 0 => Some(current - 1),
+        (true, true) => Some(0),
+        (false, true) => Some(num_desktops - 1),
+        _ => None,
+    }
+}
+
 /// Grab the pointer and let user click on a window to select it (like xwininfo)
 /// Returns the window ID of the clicked window, or None if cancelled (right-click/escape)
 fn grab_window_pick(x11: &X11Connection) -> Result<Option<u32>> {
@@ -386,7 +407,7 @@ fn grab_window_pick(x11: &X11Connection) -> Result<Option<u32>> {
     let grab_result = conn.grab_pointer(
         false,
         root,
-        (EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE).into(),
+        EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE,
         x11rb::protocol::xproto::GrabMode::ASYNC,
         x11rb::protocol::xproto::GrabMode::ASYNC,
         x11rb::NONE,
@@ -405,19 +426,16 @@ fn grab_window_pick(x11: &X11Connection) -> Result<Option<u32>> {
     // Wait for a button press
     let result = loop {
         let event = conn.wait_for_event()?;
-        match event {
-            Event::ButtonPress(ev) => {
-                if ev.detail == BUTTON_LEFT {
-                    // Left click - find the window under cursor
-                    // ev.child is the window clicked on (or 0 if root)
-                    let window = if ev.child != 0 { ev.child } else { root };
-                    break Some(window);
-                } else {
-                    // Right click or other - cancel
-                    break None;
-                }
+        if let Event::ButtonPress(ev) = event {
+            if ev.detail == BUTTON_LEFT {
+                // Left click - find the window under cursor
+                // ev.child is the window clicked on (or 0 if root)
+                let window = if ev.child != 0 { ev.child } else { root };
+                break Some(window);
+            } else {
+                // Right click or other - cancel
+                break None;
             }
-            _ => {}
         }
     };
 
@@ -9,33 +9,34 @@ use x11rb::protocol::xproto::{
 use x11rb::wrapper::ConnectionExt as WrapperConnectionExt;
 use x11rb::COPY_DEPTH_FROM_PARENT;
 
-use crate::x11::X11Connection;
+use crate::backend::Backend;
+use crate::config::PopupConfig;
+use crate::x11::{latin1, X11Connection};
 
 const POPUP_ATOM: &[u8] = b"_XDESKIE_POPUP";
-const POPUP_SIZE: u16 = 60;
-const DISPLAY_DURATION_MS: u64 = 1000;
 
-/// Show a popup window displaying the current desktop number.
+/// Show a popup window displaying the current desktop name or number.
 ///
 /// Uses X atom coordination to ensure only one popup exists at a time.
 /// When called rapidly, the previous popup is destroyed and timer resets.
-pub fn show_desktop_popup(x11: &X11Connection, desktop: u32) -> Result<()> {
+/// Size and duration come from the `[popup]` config section.
+pub fn show_desktop_popup(x11: &X11Connection, label: &str, config: &PopupConfig) -> Result<()> {
     // Destroy any existing popup window
     if let Some(old_win) = x11.get_root_property(POPUP_ATOM)? {
         let _ = x11.destroy_window(old_win);
     }
 
     // Create the popup window
-    let (win_id, gc_id) = create_popup_window(x11)?;
+    let (win_id, gc_id) = create_popup_window(x11, config.size)?;
 
     // Store window ID in atom for coordination
     x11.set_root_property(POPUP_ATOM, win_id)?;
 
-    // Draw the desktop number
-    draw_desktop_number(x11, win_id, gc_id, desktop)?;
+    // Draw the desktop label
+    draw_label(x11, win_id, gc_id, label, config.size)?;
 
     // Wait and then cleanup
-    thread::sleep(Duration::from_millis(DISPLAY_DURATION_MS));
+    thread::sleep(Duration::from_millis(config.duration_ms));
 
     // Destroy window and remove atom
     x11.destroy_window(win_id)?;
@@ -44,15 +45,15 @@ pub fn show_desktop_popup(x11: &X11Connection, desktop: u32) -> Result<()> {
     Ok(())
 }
 
-fn create_popup_window(x11: &X11Connection) -> Result<(Window, Gcontext)> {
+fn create_popup_window(x11: &X11Connection, size: u16) -> Result<(Window, Gcontext)> {
     let conn = x11.conn();
     let root = x11.root();
     let (screen_width, screen_height) = x11.screen_size();
     let (white_pixel, black_pixel) = x11.screen_pixels();
 
     // Center the window
-    let x = (screen_width.saturating_sub(POPUP_SIZE)) / 2;
-    let y = (screen_height.saturating_sub(POPUP_SIZE)) / 2;
+    let x = (screen_width.saturating_sub(size)) / 2;
+    let y = (screen_height.saturating_sub(size)) / 2;
 
     let win_id = x11.generate_id()?;
     let gc_id = conn.generate_id()?;
@@ -64,8 +65,8 @@ fn create_popup_window(x11: &X11Connection) -> Result<(Window, Gcontext)> {
         root,
         x as i16,
         y as i16,
-        POPUP_SIZE,
-        POPUP_SIZE,
+        size,
+        size,
         2,
         WindowClass::INPUT_OUTPUT,
         0,
@@ -101,22 +102,24 @@ fn create_popup_window(x11: &X11Connection) -> Result<(Window, Gcontext)> {
     Ok((win_id, gc_id))
 }
 
-fn draw_desktop_number(x11: &X11Connection, win_id: Window, gc_id: Gcontext, desktop: u32) -> Result<()> {
+fn draw_label(x11: &X11Connection, win_id: Window, gc_id: Gcontext, label: &str, size: u16) -> Result<()> {
     let conn = x11.conn();
 
-    // Draw the desktop number centered
-    // Desktop is 0-indexed internally, display as 1-indexed
-    let text = format!("{}", desktop + 1);
-
     // Approximate text centering (rough calculation for default font)
     // X11 default font is roughly 6x13 pixels per character
     let char_width = 8;
     let char_height = 13;
+
+    // Draw the desktop label centered, truncated to fit the popup
+    let max_chars = (size as i16 / char_width).max(1) as usize;
+    let label = latin1(label);
+    let text = &label[..label.len().min(max_chars)];
+
     let text_width = text.len() as i16 * char_width;
-    let text_x = (POPUP_SIZE as i16 - text_width) / 2;
-    let text_y = (POPUP_SIZE as i16 + char_height) / 2;
+    let text_x = (size as i16 - text_width) / 2;
+    let text_y = (size as i16 + char_height) / 2;
 
-    conn.image_text8(win_id, gc_id, text_x, text_y, text.as_bytes())?;
+    conn.image_text8(win_id, gc_id, text_x, text_y, text)?;
     conn.flush()?;
 
     Ok(())
@@ -0,0 +1,76 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::fingerprint::WindowFingerprint;
+use crate::x11::Geometry;
+
+/// A saved layout of running applications that can be relaunched.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Session {
+    /// Applications to relaunch, in launch order
+    pub apps: Vec<SessionApp>,
+}
+
+/// One application process and the windows it had open.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionApp {
+    /// Command line used to relaunch the application
+    pub command: Vec<String>,
+    /// Windows the application had open
+    pub windows: Vec<SessionWindow>,
+}
+
+/// Placement of a single window within a session.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionWindow {
+    /// Used to tell apart several windows of the same application
+    pub fingerprint: WindowFingerprint,
+    /// Desktop number (0=sticky, 1+=specific)
+    pub desktop: u32,
+    /// Position and size to restore
+    pub geometry: Geometry,
+}
+
+impl Session {
+    /// Load a named session.
+    pub fn load(name: &str) -> Result<Self> {
+        let path = Self::session_path(name)?;
+
+        if !path.exists() {
+            return Err(anyhow!("No saved session named '{}'", name));
+        }
+
+        let content = fs::read_to_string(&path)?;
+        let session: Session = serde_json::from_str(&content)?;
+        Ok(session)
+    }
+
+    /// Save under the given name, replacing any previous session.
+    pub fn save(&self, name: &str) -> Result<()> {
+        let path = Self::session_path(name)?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(&path, content)?;
+        Ok(())
+    }
+
+    fn session_path(name: &str) -> Result<PathBuf> {
+        if name.is_empty() || name.contains('/') || name.starts_with('.') {
+            return Err(anyhow!("Invalid session name '{}'", name));
+        }
+
+        let config_dir =
+            dirs::config_dir().ok_or_else(|| anyhow!("Cannot find config directory"))?;
+        Ok(config_dir
+            .join("xdeskie")
+            .join("sessions")
+            .join(format!("{}.json", name)))
+    }
+}
@@ -1,107 +1,499 @@
 use std::collections::{HashMap, HashSet};
-use std::fs;
-use std::path::PathBuf;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use crate::x11::X11Connection;
+use crate::backend::Backend;
+use crate::config::{Config, DesktopsConfig};
+use crate::ewmh;
+use crate::fingerprint::{RememberedWindow, WindowFingerprint};
+use crate::history::HistoryEntry;
 
-const PROP_CURRENT: &[u8] = b"_XDESKIE_CURRENT_DESKTOP";
-const PROP_COUNT: &[u8] = b"_XDESKIE_NUM_DESKTOPS";
+pub(crate) const PROP_CURRENT: &[u8] = b"_XDESKIE_CURRENT_DESKTOP";
+pub(crate) const PROP_COUNT: &[u8] = b"_XDESKIE_NUM_DESKTOPS";
+pub(crate) const PROP_WINDOW_DESKTOP: &[u8] = b"_XDESKIE_DESKTOP";
+const PROP_WINDOW_APP_HIDDEN: &[u8] = b"_XDESKIE_APP_HIDDEN";
+const PROP_SERVER_ID: &[u8] = b"_XDESKIE_SERVER_ID";
 
-const DEFAULT_DESKTOP_COUNT: u32 = 4;
+/// Where local X servers create their sockets.
+const X11_SOCKET_DIR: &str = "/tmp/.X11-unix";
+
+/// Maximum number of assignments kept for windows that have gone away.
+const MAX_REMEMBERED: usize = 100;
+
+/// Maximum number of operations that can be undone.
+const MAX_HISTORY: usize = 50;
+
+/// Schema version written to new state files.
+const STATE_VERSION: u32 = 1;
+
+/// Schema migrations, indexed by the version they upgrade from.
+///
+/// Each step rewrites the raw JSON of a version N file into version N+1.
+/// Add a step here and bump `STATE_VERSION` whenever a field is renamed or
+/// changes meaning; purely additive fields only need `#[serde(default)]`.
+const MIGRATIONS: &[fn(&mut Value) -> Result<()>] = &[
+    // 0 -> 1: unversioned files already match the version 1 layout
+    |_| Ok(()),
+];
 
 /// Persistent state for virtual desktop management.
 ///
 /// Tracks which desktop each window belongs to and synchronizes
-/// with X11 root window properties for cross-instance communication.
-#[derive(Debug, Serialize, Deserialize, Default)]
+/// with X11 properties for cross-instance communication. The root window
+/// carries the current desktop and count, and each managed window carries
+/// its own desktop assignment and app-hidden flag.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
 pub struct DesktopState {
+    /// Schema version of the state file (0 for files predating versioning)
+    #[serde(default)]
+    pub version: u32,
+    /// Marker of the X server instance window IDs belong to (0 if unknown)
+    #[serde(default)]
+    pub server_id: u32,
     /// Current desktop (0-indexed internally)
     pub current: u32,
     /// Total number of desktops
     pub desktops: u32,
+    /// Desktop names by 0-indexed desktop; missing or empty means unnamed
+    #[serde(default)]
+    pub names: Vec<String>,
     /// Window ID (as string) -> desktop number (0=sticky, 1+=specific)
     pub windows: HashMap<String, u32>,
     /// Windows hidden by the application itself (not by desktop switch)
     #[serde(default)]
     pub app_hidden: HashSet<String>,
+    /// Windows iconified through the window manager
+    #[serde(default)]
+    pub iconified: HashSet<String>,
+    /// Whether windows are hidden to show the desktop
+    #[serde(default)]
+    pub showing_desktop: bool,
+    /// Windows hidden to show the desktop, restored when that ends
+    #[serde(default)]
+    pub desktop_hidden: HashSet<String>,
+    /// Last focused window per desktop (0-indexed)
+    #[serde(default)]
+    pub focus: HashMap<u32, String>,
     /// Window stacking order per desktop (bottom to top)
     /// desktop number (0-indexed) -> ordered list of window IDs
     #[serde(default)]
     pub stacking: HashMap<u32, Vec<String>>,
+    /// Window ID (as string) -> identifying properties of the window
+    #[serde(default)]
+    pub fingerprints: HashMap<String, WindowFingerprint>,
+    /// Assignments of windows that no longer exist, oldest first
+    #[serde(default)]
+    pub remembered: Vec<RememberedWindow>,
+    /// Snapshots taken before undoable operations, oldest first
+    #[serde(default)]
+    pub undo: Vec<HistoryEntry>,
+    /// Snapshots replaced by undo, available to redo, oldest first
+    #[serde(default)]
+    pub redo: Vec<HistoryEntry>,
+    /// Desktops to start from if the file vanishes, set while transactions
+    /// reuse this state instead of reloading it
+    #[serde(skip)]
+    pub(crate) resident: Option<DesktopsConfig>,
+    /// The state file as last loaded or saved by a resident state
+    #[serde(skip)]
+    pub(crate) stamp: Option<FileStamp>,
+}
+
+/// Identifies one version of a state file. Every save renames a new file
+/// into place, so any save by another process changes the inode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FileStamp {
+    inode: u64,
+    modified: SystemTime,
+}
+
+impl FileStamp {
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(Self {
+            inode: metadata.ino(),
+            modified: metadata.modified().ok()?,
+        })
+    }
 }
 
 impl DesktopState {
-    /// Load state from file, or create default.
-    pub fn load() -> Result<Self> {
-        let path = Self::state_path()?;
+    /// Load state for the connected display and screen, or create default.
+    ///
+    /// The pre-per-display `state.json` is moved to the first display
+    /// loaded, so existing assignments carry over once instead of leaking
+    /// into every new display. A file that cannot be parsed is moved to a
+    /// timestamped backup and replaced by default state; callers then
+    /// rebuild assignments via `sync_from_x`.
+    pub fn load(backend: &impl Backend) -> Result<Self> {
+        Self::load_from(
+            &Self::state_path(backend)?,
+            Some(&Self::legacy_state_path()?),
+            &Self::configured_desktops(),
+        )
+    }
 
+    /// Load the state file at `path`, using `defaults` if there is none.
+    fn load_from(path: &Path, legacy_path: Option<&Path>, defaults: &DesktopsConfig) -> Result<Self> {
+        if let (false, Some(legacy_path)) = (path.exists(), legacy_path) {
+            Self::migrate_legacy(legacy_path, path)?;
+        }
         if !path.exists() {
-            return Ok(Self::default_state());
+            return Ok(Self::default_state(defaults));
+        }
+
+        let content = fs::read_to_string(path)?;
+        match Self::parse(&content) {
+            Ok(state) => Ok(state),
+            Err(e) => {
+                // A broken file must never lock the user out of their windows,
+                // so set it aside and rebuild from X properties instead
+                eprintln!("xdeskie: warning: state file {} is unreadable: {}", path.display(), e);
+                match Self::backup_corrupt(path) {
+                    Ok(backup) => eprintln!("xdeskie: moved it to {}", backup.display()),
+                    Err(e) => eprintln!("xdeskie: could not back it up: {}", e),
+                }
+                eprintln!("xdeskie: starting with fresh state");
+                Ok(Self::default_state(defaults))
+            }
+        }
+    }
+
+    /// Move the pre-per-display state file to a display's state path.
+    /// Another display may have claimed it first, which is not an error.
+    fn migrate_legacy(legacy_path: &Path, path: &Path) -> Result<()> {
+        if !legacy_path.exists() {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match fs::rename(legacy_path, path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Parse state file contents, migrating older schema versions.
+    fn parse(content: &str) -> Result<Self> {
+        let mut value: Value = serde_json::from_str(content)?;
+        let object = value
+            .as_object_mut()
+            .ok_or_else(|| anyhow!("expected a JSON object"))?;
+
+        let version = object.get("version").and_then(Value::as_u64).unwrap_or(0) as u32;
+        if version > STATE_VERSION {
+            eprintln!(
+                "xdeskie: warning: state file version {} is newer than supported version {}; it will not be modified",
+                version, STATE_VERSION
+            );
+        }
+
+        for migrate in MIGRATIONS.iter().skip(version as usize) {
+            migrate(&mut value)?;
         }
 
-        let content = fs::read_to_string(&path)?;
-        let state: DesktopState = serde_json::from_str(&content)?;
+        let mut state: DesktopState = serde_json::from_value(value)?;
+        if state.desktops == 0 {
+            return Err(anyhow!("desktop count is zero"));
+        }
+        state.current = state.current.min(state.desktops - 1);
+        // A newer version is kept so `save` won't drop fields we don't know
+        state.version = version.max(STATE_VERSION);
         Ok(state)
     }
 
-    fn default_state() -> Self {
+    /// Move a corrupt state file aside to a timestamped backup.
+    fn backup_corrupt(path: &Path) -> Result<PathBuf> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let backup = path.with_extension(format!("json.corrupt-{}", timestamp));
+        fs::rename(path, &backup)?;
+        Ok(backup)
+    }
+
+    /// Desktops new state starts with, from the config file.
+    fn configured_desktops() -> DesktopsConfig {
+        // The CLI warns about config errors; they must not stop state loading
+        Config::load().unwrap_or_default().desktops
+    }
+
+    fn default_state(desktops: &DesktopsConfig) -> Self {
         DesktopState {
+            version: STATE_VERSION,
+            server_id: 0,
             current: 0,
-            desktops: DEFAULT_DESKTOP_COUNT,
+            desktops: desktops.count,
+            names: desktops.names.clone(),
             windows: HashMap::new(),
             app_hidden: HashSet::new(),
+            iconified: HashSet::new(),
+            showing_desktop: false,
+            desktop_hidden: HashSet::new(),
+            focus: HashMap::new(),
             stacking: HashMap::new(),
+            fingerprints: HashMap::new(),
+            remembered: Vec::new(),
+            undo: Vec::new(),
+            redo: Vec::new(),
+            resident: None,
+            stamp: None,
         }
     }
 
-    /// Save state to file.
-    pub fn save(&self) -> Result<()> {
-        let path = Self::state_path()?;
+    /// Run a locked read-modify-write transaction on the state file.
+    ///
+    /// Holds an exclusive advisory lock while state is reloaded from disk
+    /// and X, `f` is applied, and the result is written back atomically.
+    /// On success `self` is replaced by the committed state; on error
+    /// nothing is written and `self` is left untouched. Resident state
+    /// skips the reload, see `keep_resident`.
+    pub fn transaction<T>(
+        &mut self,
+        backend: &impl Backend,
+        f: impl FnOnce(&mut DesktopState) -> Result<T>,
+    ) -> Result<T> {
+        let path = Self::state_path(backend)?;
+        let _lock = StateLock::acquire(&path)?;
 
+        if let Some(defaults) = self.resident.clone() {
+            return self.resident_transaction(backend, &path, &defaults, f);
+        }
+
+        let mut state = Self::load(backend)?;
+        state.sync_from_x(backend)?;
+
+        let result = f(&mut state)?;
+
+        state.sync_to_x(backend)?;
+        state.save_unless_newer(&path)?;
+        *self = state;
+
+        Ok(result)
+    }
+
+    /// Keep this state in memory as the authority for later transactions,
+    /// as `xdeskie daemon` does. They then apply `f` to it directly and
+    /// write it back only if it changed. The file is reloaded only after
+    /// another process saved it, and X only while a window manager owns
+    /// the desktops. `defaults` seeds the reloaded state should the file be
+    /// gone by then.
+    pub fn keep_resident(&mut self, backend: &impl Backend, defaults: &DesktopsConfig) -> Result<()> {
+        self.resident = Some(defaults.clone());
+        self.stamp = FileStamp::of(&Self::state_path(backend)?);
+        Ok(())
+    }
+
+    fn resident_transaction<T>(
+        &mut self,
+        backend: &impl Backend,
+        path: &Path,
+        defaults: &DesktopsConfig,
+        f: impl FnOnce(&mut DesktopState) -> Result<T>,
+    ) -> Result<T> {
+        if FileStamp::of(path) != self.stamp {
+            // Other processes update X before they save, so both are reread
+            let mut loaded = Self::load_from(path, None, defaults)?;
+            loaded.sync_from_x(backend)?;
+            loaded.resident = Some(defaults.clone());
+            loaded.stamp = FileStamp::of(path);
+            *self = loaded;
+        } else if backend.has_ewmh_desktops()? {
+            // The window manager switches desktops without telling us
+            self.sync_from_x(backend)?;
+        }
+
+        let mut state = self.clone();
+        let result = f(&mut state)?;
+
+        if state != *self {
+            state.sync_to_x(backend)?;
+            state.save_unless_newer(path)?;
+            state.stamp = FileStamp::of(path);
+        }
+        *self = state;
+
+        Ok(result)
+    }
+
+    /// Save state to file via a temporary file and rename, so readers
+    /// never observe a partially written state.
+    fn save(&self, path: &Path) -> Result<()> {
+        self.ensure_writable(path)?;
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent)?;
         }
 
         let content = serde_json::to_string_pretty(self)?;
-        fs::write(&path, content)?;
+        let tmp_path = path.with_extension(format!("json.tmp.{}", std::process::id()));
+
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Save state, except over a file written by a newer xdeskie: that is
+    /// left alone with a warning, and the change lives on only in X.
+    fn save_unless_newer(&self, path: &Path) -> Result<()> {
+        match self.ensure_writable(path) {
+            Ok(()) => self.save(path),
+            Err(e) => {
+                eprintln!("xdeskie: warning: {}", e);
+                Ok(())
+            }
+        }
+    }
+
+    /// Refuse to replace a state file written by a newer xdeskie, which
+    /// may hold fields this version would silently drop.
+    fn ensure_writable(&self, path: &Path) -> Result<()> {
+        if self.version > STATE_VERSION {
+            return Err(anyhow!(
+                "{} was written by a newer xdeskie (state version {}, this one supports {}); not overwriting it",
+                path.display(),
+                self.version,
+                STATE_VERSION
+            ));
+        }
         Ok(())
     }
 
     /// Sync state from X properties (for cross-instance communication).
-    pub fn sync_from_x(&mut self, x11: &X11Connection) -> Result<()> {
-        if let Some(current) = x11.get_root_property(PROP_CURRENT)? {
+    ///
+    /// The X server is the source of truth: assignments stored on windows
+    /// override whatever was loaded from the state file.
+    pub fn sync_from_x(&mut self, backend: &impl Backend) -> Result<()> {
+        self.check_server(backend)?;
+
+        if backend.has_ewmh_desktops()? {
+            return ewmh::sync_from_window_manager(backend, self);
+        }
+
+        if let Some(current) = backend.get_root_property(PROP_CURRENT)? {
             self.current = current;
         }
-        if let Some(count) = x11.get_root_property(PROP_COUNT)? {
+        if let Some(count) = backend.get_root_property(PROP_COUNT)? {
             self.desktops = count;
         }
+
+        for window in backend.get_toplevel_windows()? {
+            // Windows may disappear while we iterate; skip them
+            if let Some(desktop) = backend.get_window_property(window, PROP_WINDOW_DESKTOP).unwrap_or(None) {
+                self.set_window_desktop(window, desktop);
+            }
+            if let Some(hidden) = backend.get_window_property(window, PROP_WINDOW_APP_HIDDEN).unwrap_or(None) {
+                self.set_app_hidden(window, hidden != 0);
+            }
+        }
         Ok(())
     }
 
-    /// Write state to X properties.
-    pub fn sync_to_x(&self, x11: &X11Connection) -> Result<()> {
-        x11.set_root_property(PROP_CURRENT, self.current)?;
-        x11.set_root_property(PROP_COUNT, self.desktops)?;
+    /// Forget window IDs saved while a different X server was running.
+    ///
+    /// A restarted server hands out the same IDs to unrelated windows, so
+    /// old assignments are only remembered by fingerprint, which brings
+    /// relaunched applications back to their desktops. The server is
+    /// recognized by a random marker xdeskie leaves on its root window.
+    fn check_server(&mut self, backend: &impl Backend) -> Result<()> {
+        let server_id = match backend.get_root_property(PROP_SERVER_ID)? {
+            Some(id) => id,
+            None => {
+                let id = new_server_id();
+                backend.set_root_property(PROP_SERVER_ID, id)?;
+                id
+            }
+        };
+
+        // State saved before the marker existed is trusted
+        if self.server_id != 0 && self.server_id != server_id {
+            self.cleanup_dead_windows(&[]);
+            self.showing_desktop = false;
+            self.undo.clear();
+            self.redo.clear();
+        }
+        self.server_id = server_id;
         Ok(())
     }
 
-    /// Get desktop for a window, assigning to current desktop if new.
-    ///
-    /// Returns the desktop number (0=sticky, 1+=specific desktop).
-    pub fn get_window_desktop(&mut self, window_id: u32, current_desktop: u32) -> u32 {
-        let key = window_id.to_string();
+    /// Write state to X properties, including the EWMH desktop hints.
+    pub fn sync_to_x(&self, backend: &impl Backend) -> Result<()> {
+        backend.update_root_property(PROP_CURRENT, self.current)?;
+        backend.update_root_property(PROP_COUNT, self.desktops)?;
+
+        for (key, &desktop) in &self.windows {
+            let Ok(window) = key.parse::<u32>() else {
+                continue;
+            };
+            let hidden = self.app_hidden.contains(key) as u32;
+            backend.update_window_property(window, PROP_WINDOW_DESKTOP, desktop)?;
+            backend.update_window_property(window, PROP_WINDOW_APP_HIDDEN, hidden)?;
+        }
 
-        if let Some(&desktop) = self.windows.get(&key) {
-            return desktop;
+        // Don't overwrite the hints of a window manager that has desktops
+        if backend.has_ewmh_desktops()? {
+            return Ok(());
         }
+        ewmh::publish(backend, self)
+    }
 
-        // New window: assign to current desktop (stored as 1-indexed)
-        let assigned = current_desktop + 1;
-        self.windows.insert(key, assigned);
-        assigned
+    /// Get the name of a desktop (0-indexed), if it has one.
+    pub fn desktop_name(&self, desktop: u32) -> Option<&str> {
+        self.names
+            .get(desktop as usize)
+            .map(String::as_str)
+            .filter(|name| !name.is_empty())
+    }
+
+    /// Get the text to display for a desktop (0-indexed): its name or number.
+    pub fn desktop_label(&self, desktop: u32) -> String {
+        match self.desktop_name(desktop) {
+            Some(name) => name.to_string(),
+            None => (desktop + 1).to_string(),
+        }
+    }
+
+    /// Name a desktop (0-indexed). An empty name removes it.
+    pub fn set_desktop_name(&mut self, desktop: u32, name: &str) {
+        let index = desktop as usize;
+        if self.names.len() <= index {
+            self.names.resize(index + 1, String::new());
+        }
+        self.names[index] = name.to_string();
+
+        while self.names.last().is_some_and(String::is_empty) {
+            self.names.pop();
+        }
+    }
+
+    /// Resolve a desktop given by number or name to its 1-indexed number.
+    ///
+    /// Numbers are returned as-is without range checking. Names match
+    /// exactly first, then case-insensitively.
+    pub fn resolve_desktop(&self, spec: &str) -> Result<u32> {
+        if let Ok(number) = spec.parse::<u32>() {
+            return Ok(number);
+        }
+
+        let position = self
+            .names
+            .iter()
+            .position(|name| name == spec)
+            .or_else(|| self.names.iter().position(|name| name.eq_ignore_ascii_case(spec)));
+
+        match position {
+            Some(index) if (index as u32) < self.desktops => Ok(index as u32 + 1),
+            _ => Err(anyhow!("No desktop named '{}'", spec)),
+        }
     }
 
     /// Set desktop for a window.
@@ -109,14 +501,79 @@ impl DesktopState {
         self.windows.insert(window_id.to_string(), desktop);
     }
 
+    /// Record a snapshot before an undoable operation.
+    ///
+    /// Starting a new operation discards anything that could be redone.
+    pub fn record_history(&mut self, operation: String) {
+        let entry = self.snapshot(operation);
+        self.undo.push(entry);
+        if self.undo.len() > MAX_HISTORY {
+            let excess = self.undo.len() - MAX_HISTORY;
+            self.undo.drain(..excess);
+        }
+        self.redo.clear();
+    }
+
+    /// Capture the current assignments.
+    pub fn snapshot(&self, operation: String) -> HistoryEntry {
+        HistoryEntry {
+            operation,
+            current: self.current,
+            desktops: self.desktops,
+            windows: self.windows.clone(),
+            app_hidden: self.app_hidden.clone(),
+        }
+    }
+
+    /// Restore the desktop count and window assignments from a snapshot.
+    ///
+    /// Windows that have since been destroyed are skipped and windows that
+    /// appeared afterwards keep their assignment, unless their desktop no
+    /// longer exists; those join the desktop being restored. The current
+    /// desktop is left for the caller to switch to, so visibility can be
+    /// updated. Returns the windows whose desktop changed, with their new
+    /// desktop.
+    pub fn restore_snapshot(&mut self, entry: &HistoryEntry) -> Vec<(u32, u32)> {
+        self.desktops = entry.desktops;
+        let current = entry.current.min(entry.desktops - 1) + 1;
+
+        let mut moved = Vec::new();
+        for (key, desktop) in self.windows.iter_mut() {
+            let restored = match entry.windows.get(key) {
+                Some(&saved) => {
+                    if entry.app_hidden.contains(key) {
+                        self.app_hidden.insert(key.clone());
+                    } else {
+                        self.app_hidden.remove(key);
+                    }
+                    saved.min(entry.desktops)
+                }
+                None if *desktop > entry.desktops => current,
+                None => continue,
+            };
+
+            if *desktop != restored {
+                moved.extend(key.parse().ok().map(|window| (window, restored)));
+            }
+            *desktop = restored;
+        }
+
+        moved.sort_unstable();
+        moved
+    }
+
     /// Check if window should be visible on the given desktop.
     ///
     /// The desktop parameter is 0-indexed.
-    /// Returns false for app-hidden windows regardless of desktop.
+    /// Returns false for app-hidden, iconified and show-desktop hidden
+    /// windows regardless of desktop.
     pub fn is_visible_on(&self, window_id: u32, desktop: u32) -> bool {
         let key = window_id.to_string();
 
-        if self.app_hidden.contains(&key) {
+        if self.app_hidden.contains(&key)
+            || self.iconified.contains(&key)
+            || self.desktop_hidden.contains(&key)
+        {
             return false;
         }
 
@@ -145,20 +602,464 @@ impl DesktopState {
         self.app_hidden.contains(&window_id.to_string())
     }
 
+    /// Mark window as iconified by the window manager.
+    pub fn set_iconified(&mut self, window_id: u32, iconified: bool) {
+        let key = window_id.to_string();
+        if iconified {
+            self.iconified.insert(key);
+        } else {
+            self.iconified.remove(&key);
+        }
+    }
+
+    /// Check if window is iconified.
+    pub fn is_iconified(&self, window_id: u32) -> bool {
+        self.iconified.contains(&window_id.to_string())
+    }
+
+    /// Mark window as hidden to show the desktop.
+    pub fn set_desktop_hidden(&mut self, window_id: u32, hidden: bool) {
+        let key = window_id.to_string();
+        if hidden {
+            self.desktop_hidden.insert(key);
+        } else {
+            self.desktop_hidden.remove(&key);
+        }
+    }
+
+    /// Remember the window last focused on a desktop (0-indexed).
+    pub fn remember_focus(&mut self, desktop: u32, window_id: u32) {
+        self.focus.insert(desktop, window_id.to_string());
+    }
+
+    /// Get the window last focused on a desktop (0-indexed).
+    pub fn remembered_focus(&self, desktop: u32) -> Option<u32> {
+        self.focus.get(&desktop).and_then(|k| k.parse().ok())
+    }
+
+    /// Record the fingerprint of a tracked window.
+    pub fn set_fingerprint(&mut self, window_id: u32, fingerprint: WindowFingerprint) {
+        if fingerprint.is_identifiable() {
+            self.fingerprints.insert(window_id.to_string(), fingerprint);
+        }
+    }
+
+    /// Check if a fingerprint has been recorded for a window.
+    pub fn has_fingerprint(&self, window_id: u32) -> bool {
+        self.fingerprints.contains_key(&window_id.to_string())
+    }
+
+    /// Find the best remembered assignment for a new window and consume it.
+    ///
+    /// Each remembered entry is used at most once, so several windows of
+    /// the same application are restored to their desktops in turn.
+    pub fn take_remembered_desktop(&mut self, fingerprint: &WindowFingerprint) -> Option<u32> {
+        let mut best: Option<(usize, u32)> = None;
+
+        // Iterate newest first so ties favor the most recent assignment
+        for (index, entry) in self.remembered.iter().enumerate().rev() {
+            if let Some(score) = entry.fingerprint.match_score(fingerprint) {
+                if best.is_none_or(|(_, best_score)| score > best_score) {
+                    best = Some((index, score));
+                }
+            }
+        }
+
+        let (index, _) = best?;
+        let entry = self.remembered.remove(index);
+        Some(entry.desktop.min(self.desktops))
+    }
+
     /// Remove windows that no longer exist from state.
+    ///
+    /// Assignments of identifiable windows are remembered so they can be
+    /// restored when the application is launched again.
     pub fn cleanup_dead_windows(&mut self, live_windows: &[u32]) {
         let live_set: HashSet<String> = live_windows.iter().map(|id| id.to_string()).collect();
-        self.windows.retain(|k, _| live_set.contains(k));
+
+        let dead: Vec<String> = self
+            .windows
+            .keys()
+            .filter(|k| !live_set.contains(*k))
+            .cloned()
+            .collect();
+        for key in dead {
+            if let (Some(desktop), Some(fingerprint)) =
+                (self.windows.remove(&key), self.fingerprints.remove(&key))
+            {
+                self.remembered.push(RememberedWindow { fingerprint, desktop });
+            }
+        }
+        if self.remembered.len() > MAX_REMEMBERED {
+            let excess = self.remembered.len() - MAX_REMEMBERED;
+            self.remembered.drain(..excess);
+        }
+
+        self.fingerprints.retain(|k, _| live_set.contains(k));
         self.app_hidden.retain(|k| live_set.contains(k));
+        self.iconified.retain(|k| live_set.contains(k));
+        self.desktop_hidden.retain(|k| live_set.contains(k));
+        self.focus.retain(|_, k| live_set.contains(k));
         // Clean up stacking orders
         for order in self.stacking.values_mut() {
             order.retain(|k| live_set.contains(k));
         }
     }
 
-    fn state_path() -> Result<PathBuf> {
+    /// List the state files saved for every display and screen.
+    pub fn saved_states() -> Result<Vec<SavedState>> {
+        let dir = Self::states_dir()?;
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut states = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            let key = stem.replace("%2F", "/");
+            let Some((display, screen)) = key.rsplit_once('.') else {
+                continue;
+            };
+            let Ok(screen) = screen.parse() else {
+                continue;
+            };
+            states.push(SavedState {
+                display: display.to_string(),
+                screen,
+                path,
+            });
+        }
+
+        states.sort_by(|a, b| (&a.display, a.screen).cmp(&(&b.display, b.screen)));
+        Ok(states)
+    }
+
+    /// State file for the connected display and screen.
+    fn state_path(backend: &impl Backend) -> Result<PathBuf> {
+        let key = format!("{}.{}", backend.display_name(), backend.screen_num()).replace('/', "%2F");
+        Ok(Self::states_dir()?.join(format!("{}.json", key)))
+    }
+
+    fn states_dir() -> Result<PathBuf> {
+        let config_dir =
+            dirs::config_dir().ok_or_else(|| anyhow::anyhow!("Cannot find config directory"))?;
+        Ok(config_dir.join("xdeskie").join("states"))
+    }
+
+    fn legacy_state_path() -> Result<PathBuf> {
         let config_dir =
             dirs::config_dir().ok_or_else(|| anyhow::anyhow!("Cannot find config directory"))?;
         Ok(config_dir.join("xdeskie").join("state.json"))
     }
 }
+
+/// Make up a nonzero marker for an X server instance.
+fn new_server_id() -> u32 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    (now.as_secs() as u32 ^ now.subsec_nanos() ^ std::process::id().rotate_left(16)).max(1)
+}
+
+/// A state file belonging to one display and screen.
+#[derive(Debug)]
+pub struct SavedState {
+    /// Display name without screen number (e.g. ":0")
+    pub display: String,
+    /// Screen number on that display
+    pub screen: usize,
+    /// Location of the state file
+    pub path: PathBuf,
+}
+
+impl SavedState {
+    /// Whether this is a local display whose X server socket is gone, the
+    /// only sign that its state is stale for sure. A failed connection
+    /// alone may just mean a wrong XAUTHORITY or an unreachable host.
+    pub fn socket_is_gone(&self) -> bool {
+        self.socket_is_gone_in(Path::new(X11_SOCKET_DIR))
+    }
+
+    fn socket_is_gone_in(&self, socket_dir: &Path) -> bool {
+        let number = self
+            .display
+            .strip_prefix(':')
+            .or_else(|| self.display.strip_prefix("unix:"));
+        match number {
+            Some(number) => !socket_dir.join(format!("X{}", number)).exists(),
+            None => false,
+        }
+    }
+
+    /// Delete the state file and its lock file.
+    pub fn remove(&self) -> Result<()> {
+        fs::remove_file(&self.path)?;
+        let lock_path = self.path.with_extension("lock");
+        if lock_path.exists() {
+            fs::remove_file(lock_path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Exclusive advisory lock guarding the state file, released on drop.
+struct StateLock {
+    _file: File,
+}
+
+impl StateLock {
+    /// Block until the lock for the given state file is acquired.
+    fn acquire(state_path: &Path) -> Result<Self> {
+        let path = state_path.with_extension("lock");
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+        file.lock()?;
+
+        Ok(Self { _file: file })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::fake::{Call, FakeBackend, FakeWindow};
+
+    fn fingerprint(class: &str, title: &str) -> WindowFingerprint {
+        WindowFingerprint {
+            class: vec![class.to_lowercase(), class.to_string()],
+            title: title.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn sticky_windows_are_visible_everywhere() {
+        let mut state = DesktopState::default_state(&DesktopsConfig::default());
+        state.set_window_desktop(1, 0);
+        state.set_window_desktop(2, 2);
+
+        assert!(state.is_visible_on(1, 0));
+        assert!(state.is_visible_on(1, 3));
+        assert!(!state.is_visible_on(2, 0));
+        assert!(state.is_visible_on(2, 1));
+
+        state.set_app_hidden(1, true);
+        assert!(!state.is_visible_on(1, 0));
+    }
+
+    #[test]
+    fn x_properties_override_loaded_assignments() {
+        let backend = FakeBackend::new();
+        let mut window = FakeWindow {
+            id: 7,
+            mapped: true,
+            ..Default::default()
+        };
+        window.properties.insert("_XDESKIE_DESKTOP".to_string(), 3);
+        backend.add_fake_window(window);
+        backend.set_root_property(PROP_CURRENT, 2).unwrap();
+
+        let mut state = DesktopState::default_state(&DesktopsConfig::default());
+        state.set_window_desktop(7, 1);
+        state.sync_from_x(&backend).unwrap();
+
+        assert_eq!(state.current, 2);
+        assert_eq!(state.windows.get("7"), Some(&3));
+    }
+
+    #[test]
+    fn only_changed_properties_are_written() {
+        let backend = FakeBackend::new();
+        backend.add_window(1, "one");
+        backend.add_window(2, "two");
+        let mut state = DesktopState::default_state(&DesktopsConfig::default());
+        state.set_window_desktop(1, 1);
+        state.set_window_desktop(2, 1);
+        state.sync_to_x(&backend).unwrap();
+        backend.clear_calls();
+
+        state.set_window_desktop(2, 3);
+        state.sync_to_x(&backend).unwrap();
+
+        let writes: Vec<Call> = backend
+            .calls()
+            .into_iter()
+            .filter(|call| matches!(call, Call::SetRootProperty(..) | Call::SetWindowProperty(..)))
+            .collect();
+        assert_eq!(
+            writes,
+            vec![
+                Call::SetWindowProperty(2, "_XDESKIE_DESKTOP".to_string(), 3),
+                Call::SetWindowProperty(2, "_NET_WM_DESKTOP".to_string(), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn window_ids_from_another_x_server_are_not_trusted() {
+        let backend = FakeBackend::new();
+        backend.add_window(7, "reused id");
+
+        let mut state = DesktopState::default_state(&DesktopsConfig::default());
+        state.sync_from_x(&backend).unwrap();
+        assert_ne!(state.server_id, 0);
+        assert_eq!(backend.get_root_property(PROP_SERVER_ID).unwrap(), Some(state.server_id));
+
+        state.set_window_desktop(7, 3);
+        state.set_fingerprint(7, fingerprint("XTerm", "shell"));
+        state.sync_from_x(&backend).unwrap();
+        assert_eq!(state.windows.get("7"), Some(&3), "same server");
+
+        backend.set_root_property(PROP_SERVER_ID, state.server_id + 1).unwrap();
+        state.sync_from_x(&backend).unwrap();
+        assert!(state.windows.is_empty());
+        assert_eq!(state.take_remembered_desktop(&fingerprint("XTerm", "shell")), Some(3));
+    }
+
+    #[test]
+    fn dead_windows_are_remembered_by_fingerprint() {
+        let mut state = DesktopState::default_state(&DesktopsConfig::default());
+        state.set_window_desktop(1, 3);
+        state.set_fingerprint(1, fingerprint("XTerm", "shell"));
+        state.cleanup_dead_windows(&[]);
+
+        assert!(state.windows.is_empty());
+        assert_eq!(state.take_remembered_desktop(&fingerprint("Firefox", "shell")), None);
+        assert_eq!(state.take_remembered_desktop(&fingerprint("XTerm", "other")), Some(3));
+        assert_eq!(state.take_remembered_desktop(&fingerprint("XTerm", "shell")), None);
+    }
+
+    #[test]
+    fn resolves_desktops_by_number_or_name() {
+        let mut state = DesktopState::default_state(&DesktopsConfig::default());
+        state.set_desktop_name(1, "web");
+
+        assert_eq!(state.resolve_desktop("3").unwrap(), 3);
+        assert_eq!(state.resolve_desktop("web").unwrap(), 2);
+        assert_eq!(state.resolve_desktop("WEB").unwrap(), 2);
+        assert!(state.resolve_desktop("mail").is_err());
+        assert_eq!(state.desktop_label(0), "1");
+        assert_eq!(state.desktop_label(1), "web");
+    }
+
+    #[test]
+    fn snapshot_restores_assignments_of_live_windows() {
+        let mut state = DesktopState::default_state(&DesktopsConfig::default());
+        state.set_window_desktop(1, 1);
+        let entry = state.snapshot("move".to_string());
+
+        state.set_window_desktop(1, 4);
+        state.set_window_desktop(2, 4);
+        assert_eq!(state.restore_snapshot(&entry), vec![(1, 1)]);
+
+        assert_eq!(state.windows.get("1"), Some(&1));
+        assert_eq!(state.windows.get("2"), Some(&4));
+    }
+
+    #[test]
+    fn newer_state_files_are_never_overwritten() {
+        let state = DesktopState::parse(r#"{"version": 99, "current": 0, "desktops": 2, "windows": {}, "future": true}"#).unwrap();
+        assert_eq!(state.version, 99);
+
+        let path = std::env::temp_dir().join(format!("xdeskie-newer-test-{}.json", std::process::id()));
+        let error = state.save(&path).unwrap_err().to_string();
+        assert!(error.contains("newer xdeskie"), "{}", error);
+        assert!(!path.exists());
+
+        // Commands still run, they just aren't saved
+        state.save_unless_newer(&path).unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn legacy_state_moves_to_the_first_display_only() {
+        let dir = std::env::temp_dir().join(format!("xdeskie-state-test-{}", std::process::id()));
+        let legacy = dir.join("state.json");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&legacy, r#"{"current": 1, "desktops": 3, "windows": {"5": 2}}"#).unwrap();
+
+        let defaults = DesktopsConfig::default();
+        let first = DesktopState::load_from(&dir.join("states").join(":0.0.json"), Some(&legacy), &defaults).unwrap();
+        let second = DesktopState::load_from(&dir.join("states").join(":1.0.json"), Some(&legacy), &defaults).unwrap();
+
+        assert_eq!(first.windows.get("5"), Some(&2));
+        assert!(!legacy.exists());
+        assert!(second.windows.is_empty(), "a second display starts from defaults");
+        assert_eq!(second.current, 0);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn resident_state_is_reloaded_and_saved_only_when_needed() {
+        let dir = std::env::temp_dir().join(format!("xdeskie-resident-test-{}", std::process::id()));
+        let path = dir.join(":0.0.json");
+        let backend = FakeBackend::new();
+        let defaults = DesktopsConfig::default();
+        let mut state = DesktopState::default_state(&defaults);
+        state.resident = Some(defaults.clone());
+
+        state
+            .resident_transaction(&backend, &path, &defaults, |state| {
+                state.current = 1;
+                Ok(())
+            })
+            .unwrap();
+        let saved = FileStamp::of(&path);
+        assert!(saved.is_some());
+
+        state.resident_transaction(&backend, &path, &defaults, |_| Ok(())).unwrap();
+        assert_eq!(FileStamp::of(&path), saved, "unchanged state is not written");
+
+        // Another xdeskie process switches desktops
+        let mut other = DesktopState::load_from(&path, None, &defaults).unwrap();
+        other.current = 2;
+        other.save(&path).unwrap();
+        backend.set_root_property(PROP_CURRENT, 2).unwrap();
+
+        let current = state.resident_transaction(&backend, &path, &defaults, |state| Ok(state.current)).unwrap();
+        assert_eq!(current, 2);
+        assert_eq!(state.resident, Some(defaults));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn only_local_displays_without_a_socket_are_gone() {
+        let dir = std::env::temp_dir().join(format!("xdeskie-socket-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("X0"), "").unwrap();
+        let saved = |display: &str| SavedState {
+            display: display.to_string(),
+            screen: 0,
+            path: PathBuf::new(),
+        };
+
+        assert!(!saved(":0").socket_is_gone_in(&dir));
+        assert!(saved(":1").socket_is_gone_in(&dir));
+        assert!(saved("unix:1").socket_is_gone_in(&dir));
+        assert!(!saved("remote:1").socket_is_gone_in(&dir), "may just be unreachable");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn parses_unversioned_files_and_rejects_broken_ones() {
+        let state = DesktopState::parse(r#"{"current": 1, "desktops": 3, "windows": {"5": 2}}"#).unwrap();
+        assert_eq!(state.version, STATE_VERSION);
+        assert_eq!(state.windows.get("5"), Some(&2));
+
+        assert!(DesktopState::parse("{\"current\": 0,").is_err());
+        assert!(DesktopState::parse("[]").is_err());
+        assert!(DesktopState::parse(r#"{"current": 0, "desktops": 0, "windows": {}}"#).is_err());
+    }
+}
@@ -1,31 +1,120 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+
 use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
 use x11rb::connection::Connection;
 use x11rb::protocol::xproto::{
-    AtomEnum, ConfigureWindowAux, ConnectionExt, GetWindowAttributesReply, MapState, PropMode,
-    StackMode, Window,
+    Atom, AtomEnum, ClientMessageEvent, CloseDown, ConfigureWindowAux, ConnectionExt,
+    CreateWindowAux, EventMask, GetWindowAttributesReply, InputFocus, MapState, PropMode, StackMode, Window,
+    WindowClass,
 };
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::COPY_DEPTH_FROM_PARENT;
+use x11rb::reexports::x11rb_protocol::parse_display::parse_display;
 use x11rb::rust_connection::RustConnection;
 
+use crate::backend::Backend;
+use crate::ewmh::{
+    NET_ACTIVE_WINDOW, NET_CURRENT_DESKTOP, NET_NUMBER_OF_DESKTOPS, NET_SUPPORTED,
+    NET_SUPPORTING_WM_CHECK, NET_WM_DESKTOP, NET_WM_STATE, NET_WM_STATE_DEMANDS_ATTENTION,
+};
+use crate::fingerprint::WindowFingerprint;
+
+/// Encode text for core font requests such as `image_text8`, which take
+/// Latin-1: one byte, and one glyph, per character. Characters outside
+/// Latin-1 become '?'.
+pub fn latin1(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+        .collect()
+}
+
+/// Connection to the X server and the screen xdeskie manages.
 pub struct X11Connection {
     conn: RustConnection,
     root: Window,
     screen_num: usize,
+    display: String,
+    wm_state: Atom,
+    /// Atoms interned so far; names never change meaning on a server
+    atoms: Mutex<HashMap<Vec<u8>, Atom>>,
+}
+
+/// Position and size of a window in root coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Geometry {
+    /// Left edge
+    pub x: i16,
+    /// Top edge
+    pub y: i16,
+    /// Width in pixels
+    pub width: u16,
+    /// Height in pixels
+    pub height: u16,
 }
 
+/// A toplevel window as seen by xdeskie
 #[derive(Debug)]
 pub struct WindowInfo {
+    /// Toplevel window ID (the WM frame for reparenting window managers)
     pub id: u32,
+    /// Window title, or the hex ID if it has none
     pub name: String,
+    /// Whether the window is currently viewable
     pub is_mapped: bool,
+    /// ICCCM WM_STATE of the client window, if the window manager set one
+    pub wm_state: Option<WmState>,
+}
+
+/// ICCCM WM_STATE as maintained by the window manager on client windows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WmState {
+    /// Not managed: never mapped, or unmapped by the application
+    Withdrawn,
+    /// Shown normally
+    Normal,
+    /// Iconified by the user
+    Iconic,
 }
 
 impl X11Connection {
+    /// Connect to the display named by `$DISPLAY`
     pub fn new() -> Result<Self> {
         let (conn, screen_num) = RustConnection::connect(None)?;
         let screen = &conn.setup().roots[screen_num];
         let root = screen.root;
 
-        Ok(Self { conn, root, screen_num })
+        let parsed = parse_display(None)?;
+        let display = format!("{}:{}", parsed.host, parsed.display);
+
+        // Looked up for nearly every window while searching for clients
+        let wm_state = conn.intern_atom(false, b"WM_STATE")?.reply()?.atom;
+
+        Ok(Self {
+            conn,
+            root,
+            screen_num,
+            display,
+            wm_state,
+            atoms: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Check whether an X server is accepting connections on a display
+    pub fn display_exists(display: &str) -> bool {
+        RustConnection::connect(Some(display)).is_ok()
+    }
+
+    /// Get the atom for a name, asking the server only the first time
+    fn atom(&self, name: &[u8]) -> Result<Atom> {
+        let mut atoms = self.atoms.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(&atom) = atoms.get(name) {
+            return Ok(atom);
+        }
+        let atom = self.conn.intern_atom(false, name)?.reply()?.atom;
+        atoms.insert(name.to_vec(), atom);
+        Ok(atom)
     }
 
     /// Get reference to the X11 connection
@@ -50,33 +139,6 @@ impl X11Connection {
         (screen.white_pixel, screen.black_pixel)
     }
 
-    /// Get the currently focused window
-    pub fn get_active_window(&self) -> Result<u32> {
-        let reply = self.conn.get_input_focus()?.reply()?;
-        let focus = reply.focus;
-
-        // If focus is root or None, return error
-        if focus == self.root || focus == 0 {
-            return Err(anyhow!("No window focused"));
-        }
-
-        Ok(focus)
-    }
-
-    /// Map (show) a window
-    pub fn map_window(&self, window: u32) -> Result<()> {
-        self.conn.map_window(window)?;
-        self.conn.flush()?;
-        Ok(())
-    }
-
-    /// Unmap (hide) a window
-    pub fn unmap_window(&self, window: u32) -> Result<()> {
-        self.conn.unmap_window(window)?;
-        self.conn.flush()?;
-        Ok(())
-    }
-
     /// Get window attributes to check if mapped
     pub fn get_window_attributes(&self, window: u32) -> Result<GetWindowAttributesReply> {
         Ok(self.conn.get_window_attributes(window)?.reply()?)
@@ -88,22 +150,7 @@ impl X11Connection {
         Ok(attrs.map_state == MapState::VIEWABLE)
     }
 
-    /// Get all top-level windows (children of root that are real application windows)
-    pub fn get_toplevel_windows(&self) -> Result<Vec<u32>> {
-        let reply = self.conn.query_tree(self.root)?.reply()?;
-        let mut windows = Vec::new();
-
-        for &child in &reply.children {
-            // Check if this is a real application window
-            if self.is_application_window(child)? {
-                windows.push(child);
-            }
-        }
-
-        Ok(windows)
-    }
-
-    /// Check if window is a real application window or TWM frame containing one
+    /// Check if window is a real application window or a frame containing one
     fn is_application_window(&self, window: u32) -> Result<bool> {
         let attrs = match self.conn.get_window_attributes(window)?.reply() {
             Ok(a) => a,
@@ -126,22 +173,61 @@ impl X11Connection {
             return Ok(false);
         }
 
-        // Check if this window has WM_CLASS (direct application window)
-        if self.has_wm_class(window)? {
-            return Ok(true);
+        Ok(self.find_client(window)?.is_some())
+    }
+
+    /// Find the client window of a toplevel window using the ICCCM WM_STATE
+    /// search (as in XmuClientWindow): the window itself if it has WM_STATE,
+    /// else the nearest descendant that does. This finds clients however
+    /// deeply ctwm, fvwm or mwm nest them, and non-reparented clients.
+    ///
+    /// Window managers that don't set WM_STATE are handled by falling back
+    /// to the window or a direct child with WM_CLASS.
+    ///
+    /// Windows may be destroyed during the search; one that can no longer
+    /// be queried is not a client, and the search goes on.
+    fn find_client(&self, window: u32) -> Result<Option<u32>> {
+        if self.has_wm_state(window).unwrap_or(false) {
+            return Ok(Some(window));
         }
 
-        // Check if this is a TWM frame (has a child with WM_CLASS)
-        // TWM reparents app windows into frames
+        let mut level = vec![window];
+        while !level.is_empty() {
+            let mut next = Vec::new();
+            for parent in level {
+                let Ok(reply) = self.conn.query_tree(parent)?.reply() else {
+                    continue;
+                };
+                for &child in &reply.children {
+                    if self.has_wm_state(child).unwrap_or(false) {
+                        return Ok(Some(child));
+                    }
+                    next.push(child);
+                }
+            }
+            level = next;
+        }
+
+        if self.has_wm_class(window).unwrap_or(false) {
+            return Ok(Some(window));
+        }
         if let Ok(reply) = self.conn.query_tree(window)?.reply() {
             for &child in &reply.children {
-                if self.has_wm_class(child)? {
-                    return Ok(true); // This is a TWM frame containing an app
+                if self.has_wm_class(child).unwrap_or(false) {
+                    return Ok(Some(child));
                 }
             }
         }
 
-        Ok(false)
+        Ok(None)
+    }
+
+    /// Check if window has WM_STATE property set
+    fn has_wm_state(&self, window: u32) -> Result<bool> {
+        let reply = self.conn
+            .get_property(false, window, self.wm_state, self.wm_state, 0, 0)?
+            .reply()?;
+        Ok(reply.type_ == self.wm_state)
     }
 
     /// Check if window has WM_CLASS property set
@@ -152,20 +238,130 @@ impl X11Connection {
         Ok(reply.length > 0)
     }
 
-    /// Get window name (try _NET_WM_NAME first, then WM_NAME)
-    /// For TWM frames, looks at child windows for the name
-    pub fn get_window_name(&self, window: u32) -> Result<String> {
-        // Try to get name from this window first
-        if let Some(name) = self.get_window_name_direct(window)? {
-            return Ok(name);
+    /// Get WM_CLASS as [instance, class]
+    pub fn get_window_class(&self, window: u32) -> Result<Vec<String>> {
+        self.get_string_list(window, AtomEnum::WM_CLASS.into())
+    }
+
+    /// Get WM_WINDOW_ROLE, if set
+    pub fn get_window_role(&self, window: u32) -> Result<Option<String>> {
+        let atom = self.atom(b"WM_WINDOW_ROLE")?;
+        Ok(self.get_string_list(window, atom)?.into_iter().next())
+    }
+
+    /// Get WM_COMMAND as an argument vector
+    pub fn get_window_command(&self, window: u32) -> Result<Vec<String>> {
+        self.get_string_list(window, AtomEnum::WM_COMMAND.into())
+    }
+
+    /// Read a STRING property holding NUL-separated values
+    fn get_string_list(&self, window: u32, atom: Atom) -> Result<Vec<String>> {
+        let reply = self.conn
+            .get_property(false, window, atom, AtomEnum::STRING, 0, 1024)?
+            .reply()?;
+
+        Ok(reply
+            .value
+            .split(|&b| b == 0)
+            .filter(|s| !s.is_empty())
+            .map(|s| String::from_utf8_lossy(s).to_string())
+            .collect())
+    }
+
+    /// Get the ICCCM WM_STATE of a client window
+    pub fn get_wm_state(&self, window: u32) -> Result<Option<WmState>> {
+        let reply = self.conn
+            .get_property(false, window, self.wm_state, self.wm_state, 0, 2)?
+            .reply()?;
+
+        let state = reply.value32().and_then(|mut values| values.next());
+        Ok(match state {
+            Some(0) => Some(WmState::Withdrawn),
+            Some(1) => Some(WmState::Normal),
+            Some(3) => Some(WmState::Iconic),
+            _ => None,
+        })
+    }
+
+    /// Get the WM_HINTS window group leader of a client window
+    fn get_window_group(&self, window: u32) -> Result<Option<u32>> {
+        const WINDOW_GROUP_HINT: u32 = 1 << 6;
+
+        let reply = self.conn
+            .get_property(false, window, AtomEnum::WM_HINTS, AtomEnum::WM_HINTS, 0, 9)?
+            .reply()?;
+        let hints: Vec<u32> = reply.value32().map(Iterator::collect).unwrap_or_default();
+
+        // The group leader is the last of the nine fields
+        if hints.len() == 9 && hints[0] & WINDOW_GROUP_HINT != 0 {
+            Ok(Some(hints[8]))
+        } else {
+            Ok(None)
         }
+    }
 
-        // If this is a TWM frame, try to get name from child app window
-        if let Ok(reply) = self.conn.query_tree(window)?.reply() {
-            for &child in &reply.children {
-                if let Some(name) = self.get_window_name_direct(child)? {
-                    return Ok(name);
-                }
+    /// Whether a toplevel window's client asks for attention, with the
+    /// WM_HINTS urgency flag or _NET_WM_STATE_DEMANDS_ATTENTION
+    pub fn is_urgent(&self, window: u32) -> Result<bool> {
+        const URGENCY_HINT: u32 = 1 << 8;
+
+        let client = self.client_window(window)?;
+        let reply = self.conn
+            .get_property(false, client, AtomEnum::WM_HINTS, AtomEnum::WM_HINTS, 0, 1)?
+            .reply()?;
+        let flags = reply.value32().and_then(|mut values| values.next()).unwrap_or(0);
+        if flags & URGENCY_HINT != 0 {
+            return Ok(true);
+        }
+
+        let demands_attention = self.intern_atom(NET_WM_STATE_DEMANDS_ATTENTION)?;
+        Ok(self.get_window_atoms(client, NET_WM_STATE)?.contains(&demands_attention))
+    }
+
+    /// Get the process ID from _NET_WM_PID, if the client sets it
+    pub fn get_window_pid(&self, window: u32) -> Result<Option<u32>> {
+        self.get_window_property(window, b"_NET_WM_PID")
+    }
+
+    /// Get the geometry of a toplevel window.
+    ///
+    /// Position is taken from the toplevel (frame) window and size from
+    /// the application window inside it, which is what a ConfigureRequest
+    /// to the application window expects.
+    pub fn get_window_geometry(&self, window: u32) -> Result<Geometry> {
+        let frame = self.conn.get_geometry(window)?.reply()?;
+        let client = self.conn.get_geometry(self.client_window(window)?)?.reply()?;
+
+        Ok(Geometry {
+            x: frame.x,
+            y: frame.y,
+            width: client.width,
+            height: client.height,
+        })
+    }
+
+    /// Move and resize the application window inside a toplevel window
+    pub fn move_resize_window(&self, window: u32, geometry: &Geometry) -> Result<()> {
+        self.conn.configure_window(
+            self.client_window(window)?,
+            &ConfigureWindowAux::new()
+                .x(geometry.x as i32)
+                .y(geometry.y as i32)
+                .width(geometry.width as u32)
+                .height(geometry.height as u32),
+        )?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Get window name (try _NET_WM_NAME first, then WM_NAME)
+    /// For frames, the name of the client window inside is used
+    pub fn get_window_name(&self, window: u32) -> Result<String> {
+        // Frames rarely have a name of their own, so prefer the client's
+        let client = self.client_window(window)?;
+        for w in [client, window] {
+            if let Some(name) = self.get_window_name_direct(w)? {
+                return Ok(name);
             }
         }
 
@@ -175,8 +371,8 @@ impl X11Connection {
     /// Get window name directly from a window (not checking children)
     fn get_window_name_direct(&self, window: u32) -> Result<Option<String>> {
         // Try _NET_WM_NAME first (UTF-8)
-        let net_wm_name = self.conn.intern_atom(false, b"_NET_WM_NAME")?.reply()?.atom;
-        let utf8_string = self.conn.intern_atom(false, b"UTF8_STRING")?.reply()?.atom;
+        let net_wm_name = self.atom(b"_NET_WM_NAME")?;
+        let utf8_string = self.atom(b"UTF8_STRING")?;
 
         let reply = self.conn
             .get_property(false, window, net_wm_name, utf8_string, 0, 256)?
@@ -198,77 +394,183 @@ impl X11Connection {
         Ok(None)
     }
 
+    /// Delete a property from root window
+    pub fn delete_root_property(&self, name: &[u8]) -> Result<()> {
+        let atom = self.atom(name)?;
+        self.conn.delete_property(self.root, atom)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Destroy a window
+    pub fn destroy_window(&self, window: u32) -> Result<()> {
+        self.conn.destroy_window(window)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Generate a new window ID
+    pub fn generate_id(&self) -> Result<u32> {
+        Ok(self.conn.generate_id()?)
+    }
+
+    /// Get a list of 32-bit values of the given type from a window property
+    fn get_property32(&self, window: u32, name: &[u8], type_: AtomEnum) -> Result<Vec<u32>> {
+        let atom = self.atom(name)?;
+        let reply = self.conn
+            .get_property(false, window, atom, type_, 0, u32::MAX / 4)?
+            .reply()?;
+
+        if reply.format != 32 {
+            return Ok(Vec::new());
+        }
+
+        Ok(reply.value32().map(Iterator::collect).unwrap_or_default())
+    }
+
+    /// Get a UTF8_STRING property from a window
+    fn get_utf8_property(&self, window: u32, name: &[u8]) -> Result<Vec<u8>> {
+        let atom = self.atom(name)?;
+        let utf8_string = self.atom(b"UTF8_STRING")?;
+        let reply = self.conn
+            .get_property(false, window, atom, utf8_string, 0, 1024)?
+            .reply()?;
+        Ok(reply.value)
+    }
+
+    /// Find the _NET_SUPPORTING_WM_CHECK window, whether it belongs to the
+    /// window manager or was left by an earlier xdeskie run
+    fn find_wm_check_window(&self) -> Result<Option<u32>> {
+        let Some(&check) = self
+            .get_property32(self.root, NET_SUPPORTING_WM_CHECK, AtomEnum::WINDOW)?
+            .first()
+        else {
+            return Ok(None);
+        };
+
+        // A stale ID may have been reused by another client, so the window
+        // must still point at itself
+        let own = self
+            .get_property32(check, NET_SUPPORTING_WM_CHECK, AtomEnum::WINDOW)
+            .unwrap_or_default();
+        Ok((own.first() == Some(&check)).then_some(check))
+    }
+}
+
+/// _NET_WM_NAME of the check window xdeskie creates for itself
+const WM_CHECK_NAME: &[u8] = b"xdeskie";
+
+impl Backend for X11Connection {
+    /// Get the display name without screen number (e.g. ":0" or "host:10")
+    fn display_name(&self) -> &str {
+        &self.display
+    }
+
+    /// Get the screen number we are managing
+    fn screen_num(&self) -> usize {
+        self.screen_num
+    }
+
+    /// Get all top-level windows (children of root that are real application windows)
+    fn get_toplevel_windows(&self) -> Result<Vec<u32>> {
+        let reply = self.conn.query_tree(self.root)?.reply()?;
+        let mut windows = Vec::new();
+
+        for &child in &reply.children {
+            // Check if this is a real application window
+            if self.is_application_window(child)? {
+                windows.push(child);
+            }
+        }
+
+        Ok(windows)
+    }
+
     /// Get info about all toplevel windows
-    pub fn get_all_window_info(&self) -> Result<Vec<WindowInfo>> {
+    fn get_all_window_info(&self) -> Result<Vec<WindowInfo>> {
         let windows = self.get_toplevel_windows()?;
         let mut infos = Vec::new();
 
         for id in windows {
             let name = self.get_window_name(id).unwrap_or_else(|_| format!("0x{:x}", id));
             let is_mapped = self.is_window_mapped(id).unwrap_or(false);
-            infos.push(WindowInfo { id, name, is_mapped });
+            let wm_state = self
+                .client_window(id)
+                .and_then(|client| self.get_wm_state(client))
+                .unwrap_or(None);
+            infos.push(WindowInfo { id, name, is_mapped, wm_state });
         }
 
         Ok(infos)
     }
 
-    /// Store a value in X property on root window
-    pub fn set_root_property(&self, name: &[u8], value: u32) -> Result<()> {
-        let atom = self.conn.intern_atom(false, name)?.reply()?.atom;
-        self.conn.change_property(
-            PropMode::REPLACE,
-            self.root,
-            atom,
-            AtomEnum::CARDINAL,
-            32,
-            1,
-            &value.to_ne_bytes(),
-        )?;
-        self.conn.flush()?;
-        Ok(())
+    /// Find the application window for a toplevel window (see `find_client`)
+    fn client_window(&self, window: u32) -> Result<u32> {
+        Ok(self.find_client(window)?.unwrap_or(window))
     }
 
-    /// Get a value from X property on root window
-    pub fn get_root_property(&self, name: &[u8]) -> Result<Option<u32>> {
-        let atom = self.conn.intern_atom(false, name)?.reply()?.atom;
-        let reply = self.conn
-            .get_property(false, self.root, atom, AtomEnum::CARDINAL, 0, 1)?
-            .reply()?;
+    /// Find the root child (frame, or unframed client) containing a window
+    fn toplevel_window(&self, window: u32) -> Result<u32> {
+        let mut current = window;
+        loop {
+            let reply = self.conn.query_tree(current)?.reply()?;
+            if reply.parent == self.root {
+                return Ok(current);
+            }
+            if reply.parent == x11rb::NONE {
+                return Err(anyhow!("Window 0x{:x} is not on this screen", window));
+            }
+            current = reply.parent;
+        }
+    }
 
-        if reply.format != 32 || reply.length == 0 {
-            return Ok(None);
+    /// Find the toplevel of the window a client is transient for, or of its
+    /// group leader. Transients of the root window (group transients) and
+    /// self-references are ignored.
+    fn get_window_leader(&self, window: u32) -> Result<Option<u32>> {
+        let client = self.client_window(window)?;
+        let transient_for = self
+            .get_property32(client, b"WM_TRANSIENT_FOR", AtomEnum::WINDOW)?
+            .first()
+            .copied();
+        let group = self.get_window_group(client)?;
+
+        for leader in [transient_for, group].into_iter().flatten() {
+            if leader == x11rb::NONE || leader == self.root || leader == client {
+                continue;
+            }
+            // The leader may already be gone
+            if let Ok(toplevel) = self.toplevel_window(leader) {
+                if toplevel != window {
+                    return Ok(Some(toplevel));
+                }
+            }
         }
 
-        let values: Vec<u32> = reply
-            .value32()
-            .ok_or_else(|| anyhow!("Invalid property"))?
-            .collect();
+        Ok(None)
+    }
 
-        Ok(values.into_iter().next())
+    /// Get identifying properties of a toplevel window
+    fn get_window_fingerprint(&self, window: u32) -> Result<WindowFingerprint> {
+        WindowFingerprint::from_window(self, window)
     }
 
-    /// Delete a property from root window
-    pub fn delete_root_property(&self, name: &[u8]) -> Result<()> {
-        let atom = self.conn.intern_atom(false, name)?.reply()?.atom;
-        self.conn.delete_property(self.root, atom)?;
+    /// Map (show) a window
+    fn map_window(&self, window: u32) -> Result<()> {
+        self.conn.map_window(window)?;
         self.conn.flush()?;
         Ok(())
     }
 
-    /// Destroy a window
-    pub fn destroy_window(&self, window: u32) -> Result<()> {
-        self.conn.destroy_window(window)?;
+    /// Unmap (hide) a window
+    fn unmap_window(&self, window: u32) -> Result<()> {
+        self.conn.unmap_window(window)?;
         self.conn.flush()?;
         Ok(())
     }
 
-    /// Generate a new window ID
-    pub fn generate_id(&self) -> Result<u32> {
-        Ok(self.conn.generate_id()?)
-    }
-
     /// Get the current stacking order of all toplevel windows (bottom to top)
-    pub fn get_stacking_order(&self) -> Result<Vec<u32>> {
+    fn get_stacking_order(&self) -> Result<Vec<u32>> {
         let reply = self.conn.query_tree(self.root)?.reply()?;
         let mut windows = Vec::new();
 
@@ -283,7 +585,7 @@ impl X11Connection {
     }
 
     /// Restack windows to match the given order (bottom to top)
-    pub fn restack_windows(&self, order: &[u32]) -> Result<()> {
+    fn restack_windows(&self, order: &[u32]) -> Result<()> {
         if order.len() < 2 {
             return Ok(());
         }
@@ -300,4 +602,230 @@ impl X11Connection {
         self.conn.flush()?;
         Ok(())
     }
+
+    /// Get the currently focused window
+    fn get_active_window(&self) -> Result<u32> {
+        let reply = self.conn.get_input_focus()?.reply()?;
+        let focus = reply.focus;
+
+        // If focus is root or None, return error
+        if focus == self.root || focus == 0 {
+            return Err(anyhow!("No window focused"));
+        }
+
+        Ok(focus)
+    }
+
+    /// Focus the client window inside a toplevel window.
+    ///
+    /// Sent unchecked: a window that was only just mapped may not be
+    /// viewable yet, and the resulting error is harmless.
+    fn focus_window(&self, window: u32) -> Result<()> {
+        let client = self.client_window(window)?;
+        self.conn.set_input_focus(InputFocus::PARENT, client, x11rb::CURRENT_TIME)?;
+
+        let active = self.atom(NET_ACTIVE_WINDOW)?;
+        self.conn.change_property32(PropMode::REPLACE, self.root, active, AtomEnum::WINDOW, &[client])?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    fn focus_root(&self) -> Result<()> {
+        self.conn.set_input_focus(InputFocus::POINTER_ROOT, u32::from(InputFocus::POINTER_ROOT), x11rb::CURRENT_TIME)?;
+
+        let active = self.atom(NET_ACTIVE_WINDOW)?;
+        self.conn.change_property32(PropMode::REPLACE, self.root, active, AtomEnum::WINDOW, &[x11rb::NONE])?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Get a value from X property on root window
+    fn get_root_property(&self, name: &[u8]) -> Result<Option<u32>> {
+        self.get_window_property(self.root, name)
+    }
+
+    /// Store a value in X property on root window
+    fn set_root_property(&self, name: &[u8], value: u32) -> Result<()> {
+        self.set_window_property(self.root, name, value)
+    }
+
+    /// Get a value from X property on a window
+    fn get_window_property(&self, window: u32, name: &[u8]) -> Result<Option<u32>> {
+        let atom = self.atom(name)?;
+        let reply = self.conn
+            .get_property(false, window, atom, AtomEnum::CARDINAL, 0, 1)?
+            .reply()?;
+
+        if reply.format != 32 || reply.length == 0 {
+            return Ok(None);
+        }
+
+        let values: Vec<u32> = reply
+            .value32()
+            .ok_or_else(|| anyhow!("Invalid property"))?
+            .collect();
+
+        Ok(values.into_iter().next())
+    }
+
+    /// Store a value in X property on a window
+    fn set_window_property(&self, window: u32, name: &[u8], value: u32) -> Result<()> {
+        let atom = self.atom(name)?;
+        self.conn.change_property(
+            PropMode::REPLACE,
+            window,
+            atom,
+            AtomEnum::CARDINAL,
+            32,
+            1,
+            &value.to_ne_bytes(),
+        )?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Store a list of windows in an X property on root window
+    fn set_root_windows(&self, name: &[u8], windows: &[u32]) -> Result<()> {
+        let atom = self.atom(name)?;
+        self.conn.change_property32(PropMode::REPLACE, self.root, atom, AtomEnum::WINDOW, windows)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Store a list of atoms in an X property on root window
+    fn set_root_atoms(&self, name: &[u8], atoms: &[&[u8]]) -> Result<()> {
+        let atom = self.atom(name)?;
+        let values = atoms
+            .iter()
+            .map(|name| self.atom(name))
+            .collect::<Result<Vec<Atom>>>()?;
+        self.conn.change_property32(PropMode::REPLACE, self.root, atom, AtomEnum::ATOM, &values)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Store a list of UTF-8 strings in an X property on root window
+    fn set_root_strings(&self, name: &[u8], strings: &[String]) -> Result<()> {
+        let atom = self.atom(name)?;
+        let utf8_string = self.atom(b"UTF8_STRING")?;
+
+        // Each string is null-terminated, including the last
+        let mut value = Vec::new();
+        for s in strings {
+            value.extend_from_slice(s.as_bytes());
+            value.push(0);
+        }
+
+        self.conn.change_property8(PropMode::REPLACE, self.root, atom, utf8_string, &value)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Create the _NET_SUPPORTING_WM_CHECK window unless it already exists.
+    ///
+    /// The window has to outlive this short-lived process, so it is created
+    /// on a separate connection whose resources the server keeps after it
+    /// closes. Later runs find and reuse it.
+    fn ensure_wm_check_window(&self) -> Result<()> {
+        if self.find_wm_check_window()?.is_some() {
+            return Ok(());
+        }
+
+        let display = format!("{}.{}", self.display, self.screen_num);
+        let (conn, _) = RustConnection::connect(Some(&display))?;
+        conn.set_close_down_mode(CloseDown::RETAIN_PERMANENT)?;
+
+        let window = conn.generate_id()?;
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            self.root,
+            -1,
+            -1,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_ONLY,
+            0,
+            &CreateWindowAux::new().override_redirect(1),
+        )?;
+
+        let check = conn.intern_atom(false, NET_SUPPORTING_WM_CHECK)?.reply()?.atom;
+        let wm_name = conn.intern_atom(false, b"_NET_WM_NAME")?.reply()?.atom;
+        let utf8_string = conn.intern_atom(false, b"UTF8_STRING")?.reply()?.atom;
+        conn.change_property32(PropMode::REPLACE, window, check, AtomEnum::WINDOW, &[window])?;
+        conn.change_property8(PropMode::REPLACE, window, wm_name, utf8_string, WM_CHECK_NAME)?;
+        conn.change_property32(PropMode::REPLACE, self.root, check, AtomEnum::WINDOW, &[window])?;
+
+        // Make sure everything is processed before the connection closes
+        conn.get_input_focus()?.reply()?;
+        Ok(())
+    }
+
+    /// Check for a window manager advertising its own desktops.
+    ///
+    /// Our own check window doesn't count, nor does a window manager
+    /// whose _NET_SUPPORTED lacks the desktop hints.
+    fn has_ewmh_desktops(&self) -> Result<bool> {
+        let Some(check) = self.find_wm_check_window()? else {
+            return Ok(false);
+        };
+        if self.get_utf8_property(check, b"_NET_WM_NAME")? == WM_CHECK_NAME {
+            return Ok(false);
+        }
+
+        let supported = self.get_property32(self.root, NET_SUPPORTED, AtomEnum::ATOM)?;
+        for name in [NET_CURRENT_DESKTOP, NET_NUMBER_OF_DESKTOPS, NET_WM_DESKTOP] {
+            let atom = self.atom(name)?;
+            if !supported.contains(&atom) {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Send an EWMH client message the way pagers do, so the window
+    /// manager (or `xdeskie daemon`) receives it
+    fn send_client_message(&self, window: Option<u32>, name: &[u8], data: [u32; 5]) -> Result<()> {
+        let atom = self.atom(name)?;
+        let event = ClientMessageEvent::new(32, window.unwrap_or(self.root), atom, data);
+        self.conn.send_event(
+            false,
+            self.root,
+            EventMask::SUBSTRUCTURE_NOTIFY | EventMask::SUBSTRUCTURE_REDIRECT,
+            event,
+        )?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Get the atom for a name, creating it if needed
+    fn intern_atom(&self, name: &[u8]) -> Result<u32> {
+        self.atom(name)
+    }
+
+    /// Get an ATOM list property from a window
+    fn get_window_atoms(&self, window: u32, name: &[u8]) -> Result<Vec<u32>> {
+        self.get_property32(window, name, AtomEnum::ATOM)
+    }
+
+    /// Store a list of atoms in an X property on a window
+    fn set_window_atoms(&self, window: u32, name: &[u8], atoms: &[u32]) -> Result<()> {
+        let atom = self.atom(name)?;
+        self.conn.change_property32(PropMode::REPLACE, window, atom, AtomEnum::ATOM, atoms)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_is_encoded_one_byte_per_character() {
+        assert_eq!(latin1("Büro"), b"B\xfcro".to_vec());
+        assert_eq!(latin1("メール 2"), b"??? 2".to_vec());
+    }
 }